@@ -0,0 +1,123 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::extractor::{extract, extract_binaries};
+use crate::process::find_binary;
+
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Exclusive lock on a file in the shared cache, held until dropped.
+///
+/// Parallel test binaries share one cache directory; the lock makes one of
+/// them download and extract while the others wait and then reuse the result.
+pub struct InstallLock {
+    // The OS releases the lock when the file is closed
+    _file: File,
+}
+
+impl InstallLock {
+    pub async fn acquire(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Failed to open lock file {}", path.display()))?;
+
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock) => tokio::time::sleep(LOCK_POLL_INTERVAL).await,
+                Err(TryLockError::Error(e)) => {
+                    return Err(e).with_context(|| format!("Failed to lock {}", path.display()))
+                }
+            }
+        }
+
+        Ok(Self { _file: file })
+    }
+}
+
+// Written last into a finished install; its absence means the directory is
+// left over from an interrupted extraction.
+pub const INSTALL_MARKER: &str = ".mongo-embedded-install.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallMarker {
+    pub version: String,
+    pub archive: String,
+    pub sha256: String,
+    // Binaries extracted in selective mode, `None` for a full extraction
+    #[serde(default)]
+    pub binaries: Option<Vec<String>>,
+}
+
+impl InstallMarker {
+    pub fn read(dir: &Path) -> Result<Self> {
+        let path = dir.join(INSTALL_MARKER);
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        std::fs::write(dir.join(INSTALL_MARKER), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Whether `dir` holds a finished install that contains all `binaries`.
+pub fn is_complete_install(dir: &Path, binaries: &[String]) -> bool {
+    InstallMarker::read(dir).is_ok() && binaries.iter().all(|b| find_binary(dir, b).is_some())
+}
+
+/// Extracts `archive` into a temporary sibling of `target` and renames it
+/// into place once the marker is written, so `target` is either missing or
+/// complete. An incomplete `target` from an earlier crash is replaced. Only
+/// the marker's `binaries` are extracted if it lists any.
+pub fn install_archive(archive: &Path, target: &Path, marker: &InstallMarker) -> Result<()> {
+    let staging = staging_dir(target)?;
+    let extracted = match &marker.binaries {
+        Some(binaries) => extract_binaries(archive, &staging, binaries),
+        None => extract(archive, &staging),
+    };
+    if let Err(e) = extracted {
+        let _ = std::fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+    commit_install(&staging, target, marker)
+}
+
+/// Returns an empty temporary sibling of `target` to extract into before
+/// [`commit_install`] moves it into place.
+pub fn staging_dir(target: &Path) -> Result<PathBuf> {
+    let name = target
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Invalid install directory: {}", target.display()))?;
+    let staging = target.with_file_name(format!(".{}.tmp-{}", name, std::process::id()));
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)?;
+    }
+    Ok(staging)
+}
+
+/// Writes the marker into a fully extracted `staging` directory and renames
+/// it to `target`, replacing whatever was there.
+pub fn commit_install(staging: &Path, target: &Path, marker: &InstallMarker) -> Result<()> {
+    if let Err(e) = marker.write(staging) {
+        let _ = std::fs::remove_dir_all(staging);
+        return Err(e);
+    }
+
+    if target.exists() {
+        std::fs::remove_dir_all(target)?;
+    }
+    std::fs::rename(staging, target)?;
+    Ok(())
+}
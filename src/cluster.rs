@@ -0,0 +1,444 @@
+use anyhow::{anyhow, Result};
+use futures_util::future::join_all;
+use mongodb::bson::{doc, Bson, Document};
+use mongodb::options::Credential;
+use mongodb::Client;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::downloader::{get_os, Os};
+use crate::process::{ClusterRole, MongoProcess};
+use crate::tls::with_tls;
+use crate::{replset, setup_user, InitStatus, Launch, MongoEmbedded};
+
+// How long a stepped down primary stays ineligible for election.
+const STEP_DOWN_SECS: i64 = 60;
+
+// Per member timeout when looking for the primary; a member that does not
+// answer this quickly is treated as down.
+const MEMBER_TIMEOUT: Duration = Duration::from_secs(1);
+
+const PRIMARY_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// mongod's limit; the arbiter counts as well.
+const MAX_VOTING_MEMBERS: usize = 7;
+
+/// Election settings of one data-bearing member.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberConfig {
+    pub priority: f64,
+    pub votes: i32,
+    pub hidden: bool,
+}
+
+impl Default for MemberConfig {
+    fn default() -> Self {
+        Self { priority: 1.0, votes: 1, hidden: false }
+    }
+}
+
+impl MemberConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_priority(mut self, priority: f64) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn set_votes(mut self, votes: i32) -> Self {
+        self.votes = votes;
+        self
+    }
+
+    /// Hidden members can never become primary, so this also sets the
+    /// priority to 0.
+    pub fn set_hidden(mut self, hidden: bool) -> Self {
+        self.hidden = hidden;
+        if hidden {
+            self.priority = 0.0;
+        }
+        self
+    }
+}
+
+/// The shape of a replica set started by [`MongoCluster`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplicaSetConfig {
+    pub name: String,
+    pub members: Vec<MemberConfig>,
+    pub arbiter: bool,
+}
+
+impl ReplicaSetConfig {
+    /// `members` data-bearing members with default settings.
+    pub fn new(name: &str, members: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            members: vec![MemberConfig::default(); members],
+            arbiter: false,
+        }
+    }
+
+    /// Replaces the settings of the member at `index`, which must be less
+    /// than the member count given to [`new`](Self::new).
+    pub fn set_member(mut self, index: usize, member: MemberConfig) -> Result<Self> {
+        let count = self.members.len();
+        let slot = self
+            .members
+            .get_mut(index)
+            .ok_or_else(|| anyhow!("The replica set has no member {}, only {} members", index, count))?;
+        *slot = member;
+        Ok(self)
+    }
+
+    /// Adds an arbiter after the data-bearing members.
+    pub fn set_arbiter(mut self, arbiter: bool) -> Self {
+        self.arbiter = arbiter;
+        self
+    }
+
+    /// Rejects configs mongod would refuse in `replSetInitiate`.
+    pub fn validate(&self) -> Result<()> {
+        if self.members.is_empty() {
+            return Err(anyhow!("A replica set needs at least one data-bearing member"));
+        }
+        for (index, member) in self.members.iter().enumerate() {
+            if member.hidden && member.priority != 0.0 {
+                return Err(anyhow!(
+                    "Member {} is hidden but has priority {}; hidden members need priority 0",
+                    index,
+                    member.priority
+                ));
+            }
+            if member.votes == 0 && member.priority > 0.0 {
+                return Err(anyhow!(
+                    "Member {} has no vote but priority {}; non-voting members need priority 0",
+                    index,
+                    member.priority
+                ));
+            }
+        }
+        let voting = self.members.iter().filter(|m| m.votes > 0).count() + usize::from(self.arbiter);
+        if voting > MAX_VOTING_MEMBERS {
+            return Err(anyhow!(
+                "A replica set has at most {} voting members, this one has {}",
+                MAX_VOTING_MEMBERS,
+                voting
+            ));
+        }
+        Ok(())
+    }
+
+    /// The `replSetInitiate` config, with one host per member and the
+    /// arbiter's host last.
+    pub fn to_document(&self, hosts: &[String]) -> Document {
+        let mut members: Vec<Bson> = self
+            .members
+            .iter()
+            .zip(hosts)
+            .enumerate()
+            .map(|(id, (member, host))| {
+                Bson::Document(doc! {
+                    "_id": id as i32,
+                    "host": host,
+                    "priority": member.priority,
+                    "votes": member.votes,
+                    "hidden": member.hidden,
+                })
+            })
+            .collect();
+        if self.arbiter {
+            if let Some(host) = hosts.get(self.members.len()) {
+                members.push(Bson::Document(doc! {
+                    "_id": self.members.len() as i32,
+                    "host": host,
+                    "arbiterOnly": true,
+                }));
+            }
+        }
+        doc! { "_id": &self.name, "members": members }
+    }
+}
+
+/// One mongod of a [`MongoCluster`]. `process` is `None` while the member
+/// is killed.
+pub struct ClusterMember {
+    pub host: String,
+    pub port: u16,
+    pub db_path: PathBuf,
+    pub arbiter: bool,
+    pub hidden: bool,
+    pub process: Option<MongoProcess>,
+}
+
+/// A replica set of several mongod processes on one machine, for testing
+/// failover. Dropping it stops every member: all of them are signalled at
+/// once, so the drop blocks for at most one member's grace period.
+pub struct MongoCluster {
+    pub name: String,
+    // Seeds the driver with every visible data-bearing member
+    pub connection_string: String,
+    pub members: Vec<ClusterMember>,
+    embedded: MongoEmbedded,
+    role: Option<ClusterRole>,
+    install_root: PathBuf,
+    os: Os,
+}
+
+impl MongoCluster {
+    /// Starts every member with the settings of `embedded`, each on its own
+    /// automatic port and in its own directory under `embedded.db_path`,
+    /// then initiates the set and waits for a primary.
+    pub async fn start(embedded: &MongoEmbedded, config: ReplicaSetConfig) -> Result<Self> {
+        Self::start_with_progress(embedded, config, |_| {}).await
+    }
+
+    /// Like [`start`](Self::start), reporting download and setup progress.
+    pub async fn start_with_progress<F>(embedded: &MongoEmbedded, config: ReplicaSetConfig, mut callback: F) -> Result<Self>
+    where
+        F: FnMut(InitStatus),
+    {
+        callback(InitStatus::CheckingDB);
+        check_settings(embedded)?;
+        config.validate()?;
+        let install_root = embedded.prepare(&mut callback).await?;
+        let cluster = Self::start_from(embedded, config, None, install_root, get_os()?, &mut callback).await?;
+        callback(InitStatus::DBInitialized);
+        Ok(cluster)
+    }
+
+    // Starts the set from an install that is already in place.
+    pub(crate) async fn start_from<F>(
+        embedded: &MongoEmbedded,
+        config: ReplicaSetConfig,
+        role: Option<ClusterRole>,
+        install_root: PathBuf,
+        os: Os,
+        callback: &mut F,
+    ) -> Result<Self>
+    where
+        F: FnMut(InitStatus),
+    {
+        let mut template = embedded.clone();
+        template.resolve_tls()?;
+        if credentials(embedded).is_some() && template.key_file.is_none() {
+            template.key_file = Some(replset::write_keyfile(&embedded.db_path)?);
+        }
+        let mut cluster = MongoCluster {
+            name: config.name.clone(),
+            connection_string: String::new(),
+            members: Vec::new(),
+            embedded: template,
+            role,
+            install_root,
+            os,
+        };
+
+        let mut layout: Vec<(String, bool, bool)> = config
+            .members
+            .iter()
+            .enumerate()
+            .map(|(i, member)| (format!("member{}", i), false, member.hidden))
+            .collect();
+        if config.arbiter {
+            layout.push(("arbiter".to_string(), true, false));
+        }
+        for (dir, arbiter, hidden) in layout {
+            let db_path = embedded.db_path.join(dir);
+            let (process, _) = cluster
+                .member_settings(&db_path, 0)
+                .start_process(&cluster.install_root, &cluster.os, &Launch::Mongod(role))
+                .await?;
+            cluster.members.push(ClusterMember {
+                host: embedded.member_host(process.port),
+                port: process.port,
+                db_path,
+                arbiter,
+                hidden,
+                process: Some(process),
+            });
+        }
+
+        let hosts: Vec<String> = cluster.members.iter().map(|m| m.host.clone()).collect();
+        let tls = cluster.embedded.tls_files()?.cloned();
+        let client = replset::direct_client(&hosts[0], tls.as_ref(), embedded.startup_timeout).await?;
+        let mut document = config.to_document(&hosts);
+        if role == Some(ClusterRole::ConfigServer) {
+            document.insert("configsvr", true);
+        }
+        callback(InitStatus::InitiatingReplicaSet);
+        replset::initiate(&client, document).await?;
+        let primary = cluster.wait_for_primary(embedded.startup_timeout).await?;
+
+        // The localhost exception lets us create the first user on the primary
+        if let Some((username, password)) = credentials(embedded) {
+            let options = replset::direct_options(&cluster.members[primary].host, tls.as_ref(), embedded.startup_timeout).await?;
+            setup_user(&options, username, password, callback).await?;
+        }
+
+        let uri = replset::connection_string(&cluster.seeds(), &cluster.name, credentials(embedded));
+        cluster.connection_string = with_tls(uri, tls.as_ref());
+        Ok(cluster)
+    }
+
+    // Every visible data-bearing member.
+    fn seeds(&self) -> Vec<String> {
+        self.members
+            .iter()
+            .filter(|m| !m.arbiter && !m.hidden)
+            .map(|m| m.host.clone())
+            .collect()
+    }
+
+    /// The set as `name/host,host`, the form `addShard` and mongos'
+    /// `--configdb` expect.
+    pub fn seed_list(&self) -> String {
+        format!("{}/{}", self.name, self.seeds().join(","))
+    }
+
+    // The template settings, pointed at one member's port and db path.
+    fn member_settings(&self, db_path: &Path, port: u16) -> MongoEmbedded {
+        let mut settings = self.embedded.clone();
+        settings.db_path = db_path.to_path_buf();
+        settings.port = port;
+        settings.replica_set = Some(self.name.clone());
+        settings
+    }
+
+    fn member(&self, index: usize) -> Result<&ClusterMember> {
+        self.members
+            .get(index)
+            .ok_or_else(|| anyhow!("The replica set has no member {}", index))
+    }
+
+    /// The index of the member that currently reports itself as primary.
+    pub async fn primary(&self) -> Result<Option<usize>> {
+        for (index, member) in self.members.iter().enumerate() {
+            if member.process.is_none() || member.arbiter {
+                continue;
+            }
+            let client = replset::direct_client(&member.host, self.embedded.tls_files()?, MEMBER_TIMEOUT).await?;
+            if let Ok(reply) = client.database("admin").run_command(doc! { "hello": 1 }, None).await {
+                if reply.get_bool("isWritablePrimary").unwrap_or(false) {
+                    return Ok(Some(index));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Waits until some member is primary and returns its index.
+    pub async fn wait_for_primary(&self, timeout: Duration) -> Result<usize> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            if let Some(index) = self.primary().await? {
+                return Ok(index);
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(anyhow!("Timed out waiting for a replica set primary"));
+            }
+            tokio::time::sleep(PRIMARY_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Asks the primary to step down, which triggers an election it cannot
+    /// win for the next 60 seconds. Returns the index of the old primary;
+    /// use [`wait_for_primary`](Self::wait_for_primary) for the new one.
+    pub async fn step_down_primary(&self) -> Result<usize> {
+        let index = self
+            .primary()
+            .await?
+            .ok_or_else(|| anyhow!("The replica set has no primary to step down"))?;
+        let mut options = replset::direct_options(&self.members[index].host, self.embedded.tls_files()?, MEMBER_TIMEOUT).await?;
+        if let Some((username, password)) = credentials(&self.embedded) {
+            options.credential = Some(
+                Credential::builder()
+                    .username(username.to_string())
+                    .password(password.to_string())
+                    .source("admin".to_string())
+                    .build(),
+            );
+        }
+        let client = Client::with_options(options)?;
+        let result = client
+            .database("admin")
+            .run_command(doc! { "replSetStepDown": STEP_DOWN_SECS }, None)
+            .await;
+        match result {
+            Ok(_) => Ok(index),
+            // Before 4.2 stepping down closes every connection, including ours
+            Err(e) => match *e.kind {
+                mongodb::error::ErrorKind::Command(_) => Err(anyhow!("replSetStepDown failed: {}", e)),
+                _ => Ok(index),
+            },
+        }
+    }
+
+    /// Kills a member without a clean shutdown, like a crash.
+    pub fn kill_member(&mut self, index: usize) -> Result<()> {
+        self.member(index)?;
+        if let Some(mut process) = self.members[index].process.take() {
+            process.kill()?;
+        }
+        Ok(())
+    }
+
+    /// Starts a killed member again on its old port and db path and waits
+    /// until it is ready. It rejoins the set on its own.
+    pub async fn restart_member(&mut self, index: usize) -> Result<()> {
+        let member = self.member(index)?;
+        if member.process.is_some() {
+            return Err(anyhow!("Member {} is still running", index));
+        }
+        let settings = self.member_settings(&member.db_path, member.port);
+        let (process, _) = settings.start_process(&self.install_root, &self.os, &Launch::Mongod(self.role)).await?;
+        self.members[index].process = Some(process);
+        Ok(())
+    }
+
+    /// Stops every running member cleanly and in parallel, like
+    /// [`MongoProcess::shutdown`]. Unlike dropping the cluster, this does
+    /// not block the thread while the members exit.
+    pub async fn shutdown(&mut self, timeout: Duration) -> Result<()> {
+        let processes = self.members.iter_mut().filter_map(|m| m.process.take()).collect();
+        shutdown_all(processes, timeout).await
+    }
+
+    // Asks every running member to shut down without waiting for any.
+    pub(crate) fn begin_shutdown(&mut self) {
+        for process in self.members.iter_mut().filter_map(|m| m.process.as_mut()) {
+            process.begin_shutdown();
+        }
+    }
+}
+
+impl Drop for MongoCluster {
+    fn drop(&mut self) {
+        // Each member's own drop then only waits for its process to exit
+        self.begin_shutdown();
+    }
+}
+
+// Shuts the processes down concurrently and reports the first failure.
+pub(crate) async fn shutdown_all(mut processes: Vec<MongoProcess>, timeout: Duration) -> Result<()> {
+    let results = join_all(processes.iter_mut().map(|process| process.shutdown(timeout))).await;
+    results.into_iter().try_for_each(|result| result.map(|_| ()))
+}
+
+// Members find each other over TCP.
+pub(crate) fn check_settings(embedded: &MongoEmbedded) -> Result<()> {
+    if embedded.bind_ip.contains('/') || embedded.bind_ip.ends_with(".sock") {
+        return Err(anyhow!("A replica set needs a TCP bind_ip, not a unix socket"));
+    }
+    Ok(())
+}
+
+// The credentials set on `embedded`, if any.
+pub(crate) fn credentials(embedded: &MongoEmbedded) -> Option<(&str, &str)> {
+    match (&embedded.username, &embedded.password) {
+        (Some(username), Some(password)) => Some((username.as_str(), password.as_str())),
+        _ => None,
+    }
+}
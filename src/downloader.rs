@@ -1,22 +1,199 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
 use std::env;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::platform::{LinuxDistro, DEFAULT_LINUX_TARGET};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Os {
     Linux,
     MacOs,
     Windows,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Arch {
     X86_64,
     Aarch64,
 }
 
+#[derive(Debug, Clone)]
 pub struct MongoUrl {
     pub url: String,
     pub filename: String,
+    // Location relative to the download base URL, e.g. `linux/<filename>`
+    pub path: String,
+    // Published SHA-256 of the archive, when known up front (e.g. from a ReleaseCatalog)
+    pub sha256: Option<String>,
+}
+
+pub const DEFAULT_BASE_URL: &str = "https://fastdl.mongodb.org";
+
+// Comma separated list of mirrors, tried in order
+pub const BASE_URL_ENV: &str = "MONGO_EMBEDDED_DOWNLOAD_BASE_URL";
+
+/// Mirrors from `MONGO_EMBEDDED_DOWNLOAD_BASE_URL`, or the official download
+/// site when the variable is unset.
+pub fn default_mirrors() -> Vec<String> {
+    let from_env: Vec<String> = env::var(BASE_URL_ENV)
+        .unwrap_or_default()
+        .split(',')
+        .map(|m| m.trim().to_string())
+        .filter(|m| !m.is_empty())
+        .collect();
+    if from_env.is_empty() {
+        vec![DEFAULT_BASE_URL.to_string()]
+    } else {
+        from_env
+    }
+}
+
+impl MongoUrl {
+    /// URLs to try for this archive on the given mirror. A `file://` mirror
+    /// may either replicate the download site layout or hold the archives
+    /// directly in one directory. On the official download site this is
+    /// `url`, which a release catalog may point at another host.
+    pub fn mirror_urls(&self, base_url: &str) -> Vec<String> {
+        let base = base_url.trim_end_matches('/');
+        if base == DEFAULT_BASE_URL {
+            return vec![self.url.clone()];
+        }
+        let mut urls = vec![format!("{}/{}", base, self.path)];
+        if base.starts_with("file://") && self.path != self.filename {
+            urls.push(format!("{}/{}", base, self.filename));
+        }
+        urls
+    }
+}
+
+pub const DEFAULT_CATALOG_URL: &str = "https://downloads.mongodb.org/full.json";
+
+/// MongoDB's `full.json` release feed, used to resolve archives that are
+/// actually published instead of guessing URLs.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReleaseCatalog {
+    versions: Vec<CatalogVersion>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CatalogVersion {
+    version: String,
+    #[serde(default)]
+    downloads: Vec<CatalogDownload>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CatalogDownload {
+    #[serde(default)]
+    arch: String,
+    #[serde(default)]
+    edition: String,
+    #[serde(default)]
+    target: String,
+    archive: Option<CatalogArchive>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct CatalogArchive {
+    url: String,
+    sha256: Option<String>,
+}
+
+impl ReleaseCatalog {
+    pub async fn fetch(url: &str) -> Result<Self> {
+        let body = reqwest::get(url).await?.error_for_status()?.text().await?;
+        Self::from_json(&body).with_context(|| format!("Failed to parse release catalog from {}", url))
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let body = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read release catalog {}", path.display()))?;
+        Self::from_json(&body).with_context(|| format!("Failed to parse release catalog {}", path.display()))
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn versions(&self) -> impl Iterator<Item = &str> {
+        self.versions.iter().map(|v| v.version.as_str())
+    }
+
+    /// Resolves the archive for the running system.
+    pub fn resolve_current(&self, version: &str) -> Result<MongoUrl> {
+        let os = get_os()?;
+        let arch = get_arch()?;
+        let distro = match os {
+            Os::Linux => LinuxDistro::detect().ok(),
+            _ => None,
+        };
+        self.resolve(version, &os, &arch, distro.as_ref())
+    }
+
+    pub fn resolve(
+        &self,
+        version: &str,
+        os: &Os,
+        arch: &Arch,
+        distro: Option<&LinuxDistro>,
+    ) -> Result<MongoUrl> {
+        let release = self
+            .versions
+            .iter()
+            .find(|v| v.version == version)
+            .ok_or_else(|| anyhow!("MongoDB {} is not listed in the release catalog", version))?;
+
+        // The feed names Apple Silicon "arm64" but Linux ARM "aarch64"
+        let arch_names: &[&str] = match arch {
+            Arch::X86_64 => &["x86_64"],
+            Arch::Aarch64 => &["aarch64", "arm64"],
+        };
+        let targets: Vec<&str> = match os {
+            Os::Linux => match distro {
+                Some(distro) => distro.candidate_targets(arch),
+                None => vec![DEFAULT_LINUX_TARGET],
+            },
+            Os::MacOs => vec!["macos"],
+            Os::Windows => vec!["windows"],
+        };
+
+        for target in &targets {
+            let found = release.downloads.iter().find(|d| {
+                d.target == *target
+                    && arch_names.contains(&d.arch.as_str())
+                    && (d.edition == "targeted" || d.edition == "base")
+            });
+            if let Some(archive) = found.and_then(|d| d.archive.as_ref()) {
+                let filename = archive
+                    .url
+                    .rsplit('/')
+                    .next()
+                    .filter(|name| !name.is_empty())
+                    .ok_or_else(|| anyhow!("Invalid archive URL in release catalog: {}", archive.url))?
+                    .to_string();
+                let path = reqwest::Url::parse(&archive.url)
+                    .map(|u| u.path().trim_start_matches('/').to_string())
+                    .unwrap_or_else(|_| filename.clone());
+                return Ok(MongoUrl {
+                    url: archive.url.clone(),
+                    filename,
+                    path,
+                    sha256: archive.sha256.clone(),
+                });
+            }
+        }
+
+        Err(anyhow!(
+            "MongoDB {} has no published archive for {:?}/{:?} (targets tried: {})",
+            version,
+            os,
+            arch,
+            targets.join(", ")
+        ))
+    }
 }
 
 pub fn get_os() -> Result<Os> {
@@ -39,52 +216,49 @@ pub fn get_arch() -> Result<Arch> {
 pub fn get_download_url(version: &str) -> Result<MongoUrl> {
     let os = get_os()?;
     let arch = get_arch()?;
-
-    let (_platform, package_format) = match (&os, &arch) {
-        (Os::Linux, Arch::X86_64) => ("linux-x86_64", "tgz"),
-        (Os::Linux, Arch::Aarch64) => ("linux-aarch64", "tgz"),
-        (Os::MacOs, Arch::X86_64) => ("macos-x86_64", "tgz"),
-        (Os::MacOs, Arch::Aarch64) => ("macos-aarch64", "tgz"),
-        (Os::Windows, Arch::X86_64) => ("windows-x86_64", "zip"),
-        _ => return Err(anyhow!("Unsupported OS/Arch combination")),
+    let distro = match os {
+        // Fall back to the default target when os-release is missing,
+        // e.g. in minimal containers.
+        Os::Linux => LinuxDistro::detect().ok(),
+        _ => None,
     };
+    get_download_url_for(version, &os, &arch, distro.as_ref())
+}
 
-    // Note: This is a simplified URL constructor. 
-    // Real MongoDB URLs are more complex and depend on specific distros for Linux.
-    // We might need to handle specific linux distros. 
-    // For now, let's try to find a generic linux binary or handle specific common ones.
-    
+pub fn get_download_url_for(
+    version: &str,
+    os: &Os,
+    arch: &Arch,
+    distro: Option<&LinuxDistro>,
+) -> Result<MongoUrl> {
     // Example: https://fastdl.mongodb.org/linux/mongodb-linux-x86_64-ubuntu2004-7.0.2.tgz
     // Example: https://fastdl.mongodb.org/osx/mongodb-macos-x86_64-7.0.2.tgz
     // Example: https://fastdl.mongodb.org/windows/mongodb-windows-x86_64-7.0.2.zip
-
-    // For generic linux, we often need to specify a distro.
-    // Let's assume ubuntu2204 for now for linux x64 as a safeish default or try to detect.
-    // Or use the "generic" linux legacy if available, but modern mongo usually target distros.
-    
-    let base_url = "https://fastdl.mongodb.org";
-    
-    let _filename = match os {
-        Os::Linux => format!("mongodb-linux-{}-{}.{}", "x86_64-ubuntu2204", version, package_format), // HARDCODING ubuntu2204 for x64 linux for now
-        Os::MacOs => format!("mongodb-macos-{}-{}.{}", "x86_64", version, package_format), // Need to fix arch for mac
-        Os::Windows => format!("mongodb-windows-x86_64-{}.{}", version, package_format),
+    let arch_name = match arch {
+        Arch::X86_64 => "x86_64",
+        Arch::Aarch64 => "aarch64",
     };
 
-    // Refined logic
-    let url = match (&os, &arch) {
-        (Os::Linux, Arch::X86_64) => format!("{}/linux/mongodb-linux-x86_64-ubuntu2204-{}.tgz", base_url, version),
-        (Os::Linux, Arch::Aarch64) => format!("{}/linux/mongodb-linux-aarch64-ubuntu2204-{}.tgz", base_url, version),
-        (Os::MacOs, Arch::X86_64) => format!("{}/osx/mongodb-macos-x86_64-{}.tgz", base_url, version),
-        (Os::MacOs, Arch::Aarch64) => format!("{}/osx/mongodb-macos-aarch64-{}.tgz", base_url, version),
-        (Os::Windows, Arch::X86_64) => format!("{}/windows/mongodb-windows-x86_64-{}.zip", base_url, version),
+    let path = match (os, arch) {
+        (Os::Linux, _) => {
+            let target = match distro {
+                Some(distro) => distro.select_target(arch, version)?,
+                None => DEFAULT_LINUX_TARGET,
+            };
+            format!("linux/mongodb-linux-{}-{}-{}.tgz", arch_name, target, version)
+        }
+        (Os::MacOs, _) => format!("osx/mongodb-macos-{}-{}.tgz", arch_name, version),
+        (Os::Windows, Arch::X86_64) => format!("windows/mongodb-windows-x86_64-{}.zip", version),
         _ => return Err(anyhow!("Unsupported OS/Arch combination")),
     };
 
-    let filename = url.split('/').last().unwrap().to_string();
+    let filename = path.rsplit('/').next().unwrap().to_string();
 
     Ok(MongoUrl {
-        url,
+        url: format!("{}/{}", DEFAULT_BASE_URL, path),
         filename,
+        path,
+        sha256: None,
     })
 }
 
@@ -93,34 +267,371 @@ pub struct DownloadProgress {
     pub downloaded: u64,
     pub total: Option<u64>,
     pub percentage: Option<f32>,
+    // Bytes that were already on disk from an interrupted download
+    pub resumed_from: u64,
 }
 
 pub async fn download_file(url: &str, destination: &std::path::Path) -> Result<()> {
     download_file_with_callback(url, destination, |_| {}).await
 }
 
+/// Downloads `url` to `destination` through a `.part` file. If an earlier
+/// attempt left a `.part` file behind, the download resumes from where it
+/// stopped using a `Range` request guarded by `If-Range`, so a changed file
+/// on the server is fetched again from scratch.
 pub async fn download_file_with_callback<F>(
     url: &str,
     destination: &std::path::Path,
+    callback: F,
+) -> Result<()>
+where
+    F: FnMut(DownloadProgress),
+{
+    download_attempt(&reqwest::Client::new(), url, destination, None, callback).await
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    // Total number of attempts, including the first one
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    // Abort an attempt when no data arrives for this long
+    pub stall_timeout: Option<Duration>,
+    // Give up on the download, retries included, after this long
+    pub overall_timeout: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            stall_timeout: Some(Duration::from_secs(60)),
+            overall_timeout: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that makes a single attempt without timeouts.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            stall_timeout: None,
+            ..Self::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryEvent {
+    // The attempt that failed, starting at 1
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub delay: Duration,
+    pub error: String,
+}
+
+pub enum DownloadEvent {
+    Progress(DownloadProgress),
+    Retrying(RetryEvent),
+}
+
+#[derive(Debug)]
+pub enum DownloadTimeout {
+    // No data arrived within the stall timeout
+    Stalled(Duration),
+    // The overall timeout elapsed
+    Overall(Duration),
+}
+
+impl fmt::Display for DownloadTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadTimeout::Stalled(t) => write!(f, "Download stalled: no data received for {:?}", t),
+            DownloadTimeout::Overall(t) => write!(f, "Download did not finish within {:?}", t),
+        }
+    }
+}
+
+impl std::error::Error for DownloadTimeout {}
+
+/// Like [`download_file_with_callback`], but retries transient failures with
+/// exponential backoff. Each retry resumes from the `.part` file.
+pub async fn download_file_with_retry<F>(
+    url: &str,
+    destination: &Path,
+    policy: &RetryPolicy,
+    mut callback: F,
+) -> Result<()>
+where
+    F: FnMut(DownloadEvent),
+{
+    with_retry(policy, &mut callback, async |client, callback| {
+        download_attempt(client, url, destination, policy.stall_timeout, |p| {
+            callback(DownloadEvent::Progress(p))
+        })
+        .await
+    })
+    .await
+}
+
+/// Downloads a `.tar.gz` or `.tar.xz` archive and unpacks it on the fly into `extract_to`,
+/// so the archive never touches the disk. Returns the sha256 of the archive.
+///
+/// A failed attempt empties `extract_to` and the next one starts over, since
+/// a half-read gzip stream cannot be resumed.
+pub async fn download_and_extract_with_retry<F>(
+    url: &str,
+    extract_to: &Path,
+    binaries: Option<&[String]>,
+    policy: &RetryPolicy,
+    mut callback: F,
+) -> Result<String>
+where
+    F: FnMut(DownloadEvent),
+{
+    with_retry(policy, &mut callback, async |client, callback| {
+        if extract_to.exists() {
+            std::fs::remove_dir_all(extract_to)?;
+        }
+        stream_attempt(client, url, extract_to, binaries, policy.stall_timeout, |p| {
+            callback(DownloadEvent::Progress(p))
+        })
+        .await
+    })
+    .await
+}
+
+// Runs `attempt` until it succeeds, fails for good or the policy gives up,
+// reporting each retry through `callback`.
+async fn with_retry<T, F, A>(policy: &RetryPolicy, callback: &mut F, mut attempt: A) -> Result<T>
+where
+    F: FnMut(DownloadEvent),
+    A: AsyncFnMut(&reqwest::Client, &mut F) -> Result<T>,
+{
+    let mut builder = reqwest::Client::builder();
+    if let Some(stall) = policy.stall_timeout {
+        builder = builder.connect_timeout(stall);
+    }
+    let client = builder.build()?;
+
+    let deadline = policy.overall_timeout.map(|t| tokio::time::Instant::now() + t);
+    let mut backoff = policy.initial_backoff;
+    let mut number = 1;
+
+    loop {
+        let download = attempt(&client, callback);
+        let result = match (deadline, policy.overall_timeout) {
+            (Some(deadline), Some(limit)) => tokio::time::timeout_at(deadline, download)
+                .await
+                .unwrap_or_else(|_| Err(DownloadTimeout::Overall(limit).into())),
+            _ => download.await,
+        };
+
+        let error = match result {
+            Ok(value) => return Ok(value),
+            Err(e) => e,
+        };
+        if number >= policy.max_attempts.max(1) || !is_retryable(&error) {
+            return Err(error);
+        }
+
+        let mut delay = backoff.min(policy.max_backoff);
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+            if remaining.is_zero() {
+                return Err(error);
+            }
+            delay = delay.min(remaining);
+        }
+
+        callback(DownloadEvent::Retrying(RetryEvent {
+            attempt: number,
+            max_attempts: policy.max_attempts,
+            delay,
+            error: error.to_string(),
+        }));
+        tokio::time::sleep(delay).await;
+        backoff = backoff.saturating_mul(2);
+        number += 1;
+    }
+}
+
+/// Downloads the archive from each mirror in turn until one succeeds.
+pub async fn download_from_mirrors<F>(
+    mirrors: &[String],
+    mongo_url: &MongoUrl,
+    destination: &Path,
+    policy: &RetryPolicy,
+    mut callback: F,
+) -> Result<()>
+where
+    F: FnMut(DownloadEvent),
+{
+    let mut errors = Vec::new();
+    for url in mirrors.iter().flat_map(|m| mongo_url.mirror_urls(m)) {
+        match download_file_with_retry(&url, destination, policy, &mut callback).await {
+            Ok(()) => return Ok(()),
+            Err(e) => errors.push(format!("{}: {:#}", url, e)),
+        }
+    }
+    Err(anyhow!("Could not download {} from any mirror:\n  {}", mongo_url.filename, errors.join("\n  ")))
+}
+
+/// Streams and unpacks the archive from each mirror in turn until one
+/// succeeds. See [`download_and_extract_with_retry`].
+pub async fn download_and_extract_from_mirrors<F>(
+    mirrors: &[String],
+    mongo_url: &MongoUrl,
+    extract_to: &Path,
+    binaries: Option<&[String]>,
+    policy: &RetryPolicy,
+    mut callback: F,
+) -> Result<String>
+where
+    F: FnMut(DownloadEvent),
+{
+    let mut errors = Vec::new();
+    for url in mirrors.iter().flat_map(|m| mongo_url.mirror_urls(m)) {
+        match download_and_extract_with_retry(&url, extract_to, binaries, policy, &mut callback).await {
+            Ok(sha256) => return Ok(sha256),
+            Err(e) => errors.push(format!("{}: {:#}", url, e)),
+        }
+    }
+    Err(anyhow!("Could not download {} from any mirror:\n  {}", mongo_url.filename, errors.join("\n  ")))
+}
+
+// `Some(path)` for `file://` URLs, `None` for anything else.
+fn local_path(url: &str) -> Result<Option<PathBuf>> {
+    if !url.starts_with("file://") {
+        return Ok(None);
+    }
+    let parsed = reqwest::Url::parse(url)?;
+    let path = parsed
+        .to_file_path()
+        .map_err(|_| anyhow!("Invalid file URL: {}", url))?;
+    Ok(Some(path))
+}
+
+// Network trouble and server side errors are worth another attempt, client
+// errors such as 404 and local I/O errors are not.
+fn is_retryable(error: &anyhow::Error) -> bool {
+    if let Some(timeout) = error.downcast_ref::<DownloadTimeout>() {
+        return matches!(timeout, DownloadTimeout::Stalled(_));
+    }
+    if let Some(e) = error.downcast_ref::<reqwest::Error>() {
+        if let Some(status) = e.status() {
+            return status.is_server_error()
+                || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                || status == reqwest::StatusCode::REQUEST_TIMEOUT;
+        }
+        return e.is_timeout() || e.is_connect() || e.is_request() || e.is_body() || e.is_decode();
+    }
+    false
+}
+
+// Applies the stall timeout, if any, to a single network read.
+async fn with_stall_timeout<T>(
+    stall_timeout: Option<Duration>,
+    future: impl std::future::Future<Output = reqwest::Result<T>>,
+) -> Result<T> {
+    match stall_timeout {
+        Some(t) => match tokio::time::timeout(t, future).await {
+            Ok(result) => Ok(result?),
+            Err(_) => Err(DownloadTimeout::Stalled(t).into()),
+        },
+        None => Ok(future.await?),
+    }
+}
+
+async fn download_attempt<F>(
+    client: &reqwest::Client,
+    url: &str,
+    destination: &Path,
+    stall_timeout: Option<Duration>,
     mut callback: F,
 ) -> Result<()>
 where
     F: FnMut(DownloadProgress),
 {
     use std::io::Write;
-    use std::fs::File;
+    use std::fs::{File, OpenOptions};
+    use reqwest::header::{CONTENT_RANGE, IF_RANGE, RANGE};
+    use reqwest::StatusCode;
 
-    let response = reqwest::get(url).await?;
-    let total = response.content_length();
+    let part_path = destination.with_extension("part");
+    let validator_path = destination.with_extension("part-validator");
+
+    if let Some(source) = local_path(url)? {
+        let size = std::fs::copy(&source, &part_path)
+            .with_context(|| format!("Failed to copy {}", source.display()))?;
+        callback(DownloadProgress {
+            downloaded: size,
+            total: Some(size),
+            percentage: Some(100.0),
+            resumed_from: 0,
+        });
+        std::fs::rename(part_path, destination)?;
+        return Ok(());
+    }
+
+    let mut resume = resume_state(&part_path, &validator_path);
+
+    let (response, mut file, resumed_from, total) = loop {
+        let mut request = client.get(url);
+        if let Some((offset, validator)) = &resume {
+            request = request
+                .header(RANGE, format!("bytes={}-", offset))
+                .header(IF_RANGE, validator.as_str());
+        }
+        let response = with_stall_timeout(stall_timeout, request.send()).await?;
 
-    let mut part_path = destination.to_path_buf();
-    part_path.set_extension("part");
+        if let Some((offset, _)) = &resume {
+            match response.status() {
+                StatusCode::PARTIAL_CONTENT => {
+                    let range = response
+                        .headers()
+                        .get(CONTENT_RANGE)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(parse_content_range);
+                    if let Some((start, total)) = range {
+                        if start == *offset {
+                            let file = OpenOptions::new().append(true).open(&part_path)?;
+                            break (response, file, start, total);
+                        }
+                    }
+                    // The server answered with a range we did not ask for
+                    resume = None;
+                    continue;
+                }
+                StatusCode::RANGE_NOT_SATISFIABLE => {
+                    resume = None;
+                    continue;
+                }
+                // Anything else, e.g. 200 because the file changed or ranges
+                // are unsupported, is a full response we can use directly.
+                _ => {}
+            }
+        }
 
-    let mut file = File::create(&part_path)?;
-    let mut downloaded: u64 = 0;
+        let response = response.error_for_status()?;
+        let total = response.content_length();
+        match response_validator(&response) {
+            Some(validator) => std::fs::write(&validator_path, validator)?,
+            None => {
+                let _ = std::fs::remove_file(&validator_path);
+            }
+        }
+        let file = File::create(&part_path)?;
+        break (response, file, 0, total);
+    };
 
+    let mut downloaded = resumed_from;
     let mut stream = response;
-    while let Some(chunk) = stream.chunk().await? {
+    while let Some(chunk) = with_stall_timeout(stall_timeout, stream.chunk()).await? {
         file.write_all(&chunk)?;
         downloaded += chunk.len() as u64;
 
@@ -129,10 +640,262 @@ where
             downloaded,
             total,
             percentage,
+            resumed_from,
         });
     }
 
     std::fs::rename(part_path, destination)?;
+    let _ = std::fs::remove_file(&validator_path);
 
     Ok(())
 }
+
+// Feeds the response body through a channel into a blocking thread that
+// hashes and unpacks it, so network reads and disk writes overlap.
+async fn stream_attempt<F>(
+    client: &reqwest::Client,
+    url: &str,
+    extract_to: &Path,
+    binaries: Option<&[String]>,
+    stall_timeout: Option<Duration>,
+    mut callback: F,
+) -> Result<String>
+where
+    F: FnMut(DownloadProgress),
+{
+    let source = url.to_string();
+    let extract_to = extract_to.to_path_buf();
+    let binaries = binaries.map(|b| b.to_vec());
+    let unpack = move |reader: Box<dyn std::io::Read + Send>| -> Result<String> {
+        let mut reader = HashingReader { inner: reader, hasher: Sha256::new() };
+        crate::extractor::extract_tar_from_reader(&mut reader, &source, &extract_to, binaries.as_deref())?;
+        // tar stops at its end-of-archive block, the digest covers everything
+        std::io::copy(&mut reader, &mut std::io::sink())?;
+        Ok(hex::encode(reader.hasher.finalize()))
+    };
+
+    if let Some(path) = local_path(url)? {
+        let file = std::fs::File::open(&path).with_context(|| format!("Failed to open {}", path.display()))?;
+        let size = file.metadata()?.len();
+        let sha256 = tokio::task::spawn_blocking(move || unpack(Box::new(file))).await??;
+        callback(DownloadProgress {
+            downloaded: size,
+            total: Some(size),
+            percentage: Some(100.0),
+            resumed_from: 0,
+        });
+        return Ok(sha256);
+    }
+
+    let response = with_stall_timeout(stall_timeout, client.get(url).send()).await?;
+    let mut response = response.error_for_status()?;
+    let total = response.content_length();
+
+    let (sender, receiver) = tokio::sync::mpsc::channel(STREAM_CHANNEL_CHUNKS);
+    let reader = ChannelReader { receiver, chunk: bytes::Bytes::new() };
+    let unpacker = tokio::task::spawn_blocking(move || unpack(Box::new(reader)));
+
+    let mut downloaded = 0;
+    let mut failure = None;
+    loop {
+        let chunk = match with_stall_timeout(stall_timeout, response.chunk()).await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => {
+                failure = Some(e);
+                break;
+            }
+        };
+        downloaded += chunk.len() as u64;
+        // The unpacker hung up, its own error explains why
+        if sender.send(Ok(chunk)).await.is_err() {
+            break;
+        }
+        callback(DownloadProgress {
+            downloaded,
+            total,
+            percentage: total.map(|t| (downloaded as f32 / t as f32) * 100.0),
+            resumed_from: 0,
+        });
+    }
+
+    if failure.is_some() {
+        let interrupted = std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "download interrupted");
+        let _ = sender.send(Err(interrupted)).await;
+    }
+    drop(sender);
+    let unpacked = unpacker.await?;
+    match failure {
+        Some(e) => Err(e),
+        None => unpacked,
+    }
+}
+
+// Chunks buffered between the network and the unpacker.
+const STREAM_CHANNEL_CHUNKS: usize = 16;
+
+type StreamChunk = std::io::Result<bytes::Bytes>;
+
+struct ChannelReader {
+    receiver: tokio::sync::mpsc::Receiver<StreamChunk>,
+    chunk: bytes::Bytes,
+}
+
+impl std::io::Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => self.chunk = chunk?,
+                // Sender dropped: end of the body
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk.split_to(n));
+        Ok(n)
+    }
+}
+
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: std::io::Read> std::io::Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+// A `.part` file is only resumable if we recorded the validator of the
+// response it came from.
+fn resume_state(part_path: &Path, validator_path: &Path) -> Option<(u64, String)> {
+    let offset = std::fs::metadata(part_path).ok()?.len();
+    let validator = std::fs::read_to_string(validator_path).ok()?;
+    let validator = validator.trim();
+    if offset == 0 || validator.is_empty() {
+        return None;
+    }
+    Some((offset, validator.to_string()))
+}
+
+// If-Range needs a strong ETag, otherwise Last-Modified is used.
+fn response_validator(response: &reqwest::Response) -> Option<String> {
+    use reqwest::header::{ETAG, LAST_MODIFIED};
+
+    let headers = response.headers();
+    headers
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| headers.get(LAST_MODIFIED).and_then(|v| v.to_str().ok()))
+        .map(|v| v.to_string())
+}
+
+// Parses `bytes <start>-<end>/<total>` into the start offset and total size.
+fn parse_content_range(value: &str) -> Option<(u64, Option<u64>)> {
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let start = range.split_once('-')?.0.trim().parse().ok()?;
+    Some((start, total.trim().parse().ok()))
+}
+
+/// Returned when an archive does not match its published digest. `path` is
+/// a downloaded archive, which has been deleted; with streaming extraction
+/// it is the staging directory the archive was unpacked into, which has
+/// been removed. An offline archive you provided is never deleted.
+#[derive(Debug)]
+pub struct ChecksumMismatch {
+    pub path: PathBuf,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Checksum mismatch for {}: expected sha256 {}, got {}",
+            self.path.display(),
+            self.expected,
+            self.actual
+        )
+    }
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Parses a `.sha256` sidecar, which holds `<hex digest>  <filename>`.
+pub fn parse_sha256_sidecar(contents: &str) -> Result<String> {
+    let digest = contents
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| anyhow!("Empty checksum file"))?;
+    if digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("Invalid sha256 digest in checksum file: {}", digest));
+    }
+    Ok(digest.to_lowercase())
+}
+
+/// Fetches the `.sha256` sidecar published next to `archive_url`.
+pub async fn fetch_sha256(archive_url: &str) -> Result<String> {
+    let sidecar_url = format!("{}.sha256", archive_url);
+    let body = if let Some(path) = local_path(&sidecar_url)? {
+        std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read checksum from {}", path.display()))?
+    } else {
+        reqwest::get(&sidecar_url)
+            .await?
+            .error_for_status()
+            .with_context(|| format!("Failed to fetch checksum from {}", sidecar_url))?
+            .text()
+            .await?
+    };
+    parse_sha256_sidecar(&body)
+}
+
+/// Fetches the `.sha256` sidecar from the first mirror that has it.
+pub async fn fetch_sha256_from_mirrors(mirrors: &[String], mongo_url: &MongoUrl) -> Result<String> {
+    let mut errors = Vec::new();
+    for url in mirrors.iter().flat_map(|m| mongo_url.mirror_urls(m)) {
+        match fetch_sha256(&url).await {
+            Ok(digest) => return Ok(digest),
+            Err(e) => errors.push(format!("{}: {:#}", url, e)),
+        }
+    }
+    Err(anyhow!("Could not fetch checksum for {}:\n  {}", mongo_url.filename, errors.join("\n  ")))
+}
+
+/// Checks `path` against `expected` and returns the actual digest.
+pub fn check_checksum(path: &Path, expected: &str) -> Result<String> {
+    let actual = sha256_file(path)?;
+    if !actual.eq_ignore_ascii_case(expected.trim()) {
+        return Err(ChecksumMismatch {
+            path: path.to_path_buf(),
+            expected: expected.trim().to_lowercase(),
+            actual,
+        }
+        .into());
+    }
+    Ok(actual)
+}
+
+/// Like [`check_checksum`], but a file that does not match is deleted so
+/// that the next attempt downloads it again.
+pub fn verify_checksum(path: &Path, expected: &str) -> Result<String> {
+    let result = check_checksum(path, expected);
+    if let Err(e) = &result {
+        if e.is::<ChecksumMismatch>() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    result
+}
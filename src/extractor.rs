@@ -1,33 +1,314 @@
 use anyhow::{anyhow, Result};
-use std::path::Path;
+use std::fmt;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
 use std::fs::File;
 use flate2::read::GzDecoder;
-use tar::Archive;
+use lzma_rust2::XzReader;
+use tar::{Archive, EntryType};
+
+/// Returned when an archive entry would be written outside the extraction
+/// directory. Nothing from the offending entry is written.
+#[derive(Debug)]
+pub enum ExtractError {
+    // Absolute path or `..` component
+    UnsafePath { entry: String },
+    // Symlink or hard link whose target lies outside the extraction directory
+    LinkEscape { entry: String, target: String },
+    // Path that passes through a symlink created by an earlier entry
+    ThroughSymlink { entry: String },
+}
+
+impl ExtractError {
+    pub fn entry(&self) -> &str {
+        match self {
+            ExtractError::UnsafePath { entry }
+            | ExtractError::LinkEscape { entry, .. }
+            | ExtractError::ThroughSymlink { entry } => entry,
+        }
+    }
+}
+
+impl fmt::Display for ExtractError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtractError::UnsafePath { entry } => {
+                write!(f, "Archive entry {} has an absolute path or `..` component", entry)
+            }
+            ExtractError::LinkEscape { entry, target } => {
+                write!(f, "Archive entry {} links to {} outside the extraction directory", entry, target)
+            }
+            ExtractError::ThroughSymlink { entry } => {
+                write!(f, "Archive entry {} would be written through a symlink", entry)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExtractError {}
+
+/// Returned when an archive is neither gzip, xz nor zip compressed.
+#[derive(Debug)]
+pub struct UnsupportedFormat {
+    pub source: String,
+}
+
+impl fmt::Display for UnsupportedFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Unsupported archive format: {} is not a .tar.gz, .tar.xz or .zip archive", self.source)
+    }
+}
+
+impl std::error::Error for UnsupportedFormat {}
+
+/// Archive formats MongoDB and its tools are published in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    TarGz,
+    TarXz,
+    Zip,
+}
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+impl ArchiveFormat {
+    /// Identifies the format from the leading bytes of the archive. File
+    /// names are unreliable: `Path::extension` sees `gz` in `.tar.gz` and
+    /// some mirrors drop the extension entirely.
+    pub fn from_magic(header: &[u8]) -> Option<Self> {
+        if header.starts_with(GZIP_MAGIC) {
+            Some(ArchiveFormat::TarGz)
+        } else if header.starts_with(XZ_MAGIC) {
+            Some(ArchiveFormat::TarXz)
+        } else if header.starts_with(ZIP_MAGIC) {
+            Some(ArchiveFormat::Zip)
+        } else {
+            None
+        }
+    }
+
+    pub fn detect(archive_path: &Path) -> Result<Self> {
+        let mut header = Vec::with_capacity(XZ_MAGIC.len());
+        File::open(archive_path)?
+            .take(XZ_MAGIC.len() as u64)
+            .read_to_end(&mut header)?;
+        Self::from_magic(&header).ok_or_else(|| {
+            UnsupportedFormat { source: archive_path.display().to_string() }.into()
+        })
+    }
+}
 
 pub fn extract(archive_path: &Path, extract_to: &Path) -> Result<()> {
+    extract_with(archive_path, extract_to, None)
+}
+
+/// Extracts only the named binaries (e.g. `mongod`, `mongos.exe`) from the
+/// archive's `bin` directory into `extract_to/bin`, skipping everything else.
+pub fn extract_binaries(archive_path: &Path, extract_to: &Path, binaries: &[String]) -> Result<()> {
+    extract_with(archive_path, extract_to, Some(binaries))
+}
+
+fn extract_with(archive_path: &Path, extract_to: &Path, binaries: Option<&[String]>) -> Result<()> {
     if !extract_to.exists() {
         std::fs::create_dir_all(extract_to)?;
     }
 
-    let extension = archive_path
-        .extension()
-        .and_then(|e| e.to_str())
-        .ok_or_else(|| anyhow!("Unknown file extension"))?;
+    let file = File::open(archive_path)?;
+    match ArchiveFormat::detect(archive_path)? {
+        ArchiveFormat::TarGz => unpack_tar(Archive::new(GzDecoder::new(file)), extract_to, binaries)?,
+        ArchiveFormat::TarXz => unpack_tar(Archive::new(XzReader::new(file, true)), extract_to, binaries)?,
+        ArchiveFormat::Zip => unpack_zip(zip::ZipArchive::new(file)?, extract_to, binaries)?,
+    }
+
+    check_selected(&archive_path.display().to_string(), extract_to, binaries)
+}
 
-    match extension {
-        "tgz" | "gz" => {
-            let file = File::open(archive_path)?;
-            let tar = GzDecoder::new(file);
-            let mut archive = Archive::new(tar);
-            archive.unpack(extract_to)?;
+/// Unpacks a gzip or xz compressed tar stream, e.g. an HTTP body, without
+/// the archive ever touching the disk. Zip archives need random access and
+/// are rejected. `binaries` works as in [`extract_binaries`].
+pub fn extract_tar_from_reader<R: Read>(mut reader: R, source: &str, extract_to: &Path, binaries: Option<&[String]>) -> Result<()> {
+    let mut header = Vec::with_capacity(XZ_MAGIC.len());
+    (&mut reader).take(XZ_MAGIC.len() as u64).read_to_end(&mut header)?;
+    // Put the sniffed bytes back in front of the rest of the stream
+    let reader = std::io::Cursor::new(header).chain(reader);
+
+    std::fs::create_dir_all(extract_to)?;
+    match ArchiveFormat::from_magic(reader.get_ref().0.get_ref()) {
+        Some(ArchiveFormat::TarGz) => unpack_tar(Archive::new(GzDecoder::new(reader)), extract_to, binaries)?,
+        Some(ArchiveFormat::TarXz) => unpack_tar(Archive::new(XzReader::new(reader, true)), extract_to, binaries)?,
+        _ => return Err(UnsupportedFormat { source: source.to_string() }.into()),
+    }
+    check_selected(source, extract_to, binaries)
+}
+
+fn check_selected(source: &str, extract_to: &Path, binaries: Option<&[String]>) -> Result<()> {
+    for binary in binaries.unwrap_or_default() {
+        if !extract_to.join("bin").join(binary).is_file() {
+            return Err(anyhow!("Archive {} does not contain bin/{}", source, binary));
         }
-        "zip" => {
-            let file = File::open(archive_path)?;
-            let mut archive = zip::ZipArchive::new(file)?;
-            archive.extract(extract_to)?;
+    }
+    Ok(())
+}
+
+// Where a selected binary goes: `bin/<name>` for any `.../bin/<name>` entry
+// whose name was asked for, `None` for everything else.
+fn selected_destination(relative: &Path, binaries: &[String]) -> Option<PathBuf> {
+    let name = relative.file_name()?.to_str()?;
+    let in_bin = relative.parent()?.file_name()? == "bin";
+    if in_bin && binaries.iter().any(|b| b == name) {
+        Some(Path::new("bin").join(name))
+    } else {
+        None
+    }
+}
+
+fn unpack_tar<R: Read>(mut archive: Archive<R>, extract_to: &Path, binaries: Option<&[String]>) -> Result<()> {
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+        let relative = safe_relative_path(&name, &entry.path()?)?;
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+
+        let entry_type = entry.header().entry_type();
+        if let Some(binaries) = binaries {
+            if entry_type.is_file() {
+                if let Some(destination) = selected_destination(&relative, binaries) {
+                    let target = extract_to.join(destination);
+                    std::fs::create_dir_all(target.parent().unwrap())?;
+                    entry.unpack(&target)?;
+                }
+            }
+            continue;
+        }
+
+        if entry_type == EntryType::Symlink || entry_type == EntryType::Link {
+            let target = entry
+                .link_name()?
+                .ok_or_else(|| anyhow!("Archive entry {} is a link without a target", name))?
+                .into_owned();
+            // Symlinks resolve against their own directory, hard links
+            // against the archive root
+            let base = match entry_type {
+                EntryType::Symlink => relative.parent().unwrap_or(Path::new("")),
+                _ => Path::new(""),
+            };
+            check_link_target(&name, base, &target)?;
+        }
+
+        check_no_symlink_ancestors(&name, extract_to, &relative)?;
+        entry.unpack_in(extract_to)?;
+    }
+    Ok(())
+}
+
+fn unpack_zip<R: Read + std::io::Seek>(mut archive: zip::ZipArchive<R>, extract_to: &Path, binaries: Option<&[String]>) -> Result<()> {
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = file.name().to_string();
+        let relative = safe_relative_path(&name, Path::new(&name))?;
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+
+        let target = match binaries {
+            Some(binaries) => match selected_destination(&relative, binaries) {
+                Some(destination) if file.is_file() => extract_to.join(destination),
+                _ => continue,
+            },
+            None => {
+                check_no_symlink_ancestors(&name, extract_to, &relative)?;
+                extract_to.join(&relative)
+            }
+        };
+
+        if file.is_dir() {
+            std::fs::create_dir_all(&target)?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        if file.is_symlink() {
+            let mut link = String::new();
+            file.read_to_string(&mut link)?;
+            check_link_target(&name, relative.parent().unwrap_or(Path::new("")), Path::new(&link))?;
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&link, &target)?;
+            #[cfg(not(unix))]
+            std::fs::write(&target, link)?;
+            continue;
+        }
+
+        let mut out = File::create(&target)?;
+        std::io::copy(&mut file, &mut out)?;
+
+        #[cfg(unix)]
+        if let Some(mode) = file.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&target, std::fs::Permissions::from_mode(mode & 0o777))?;
         }
-        _ => return Err(anyhow!("Unsupported archive format: {}", extension)),
     }
+    Ok(())
+}
 
+// Turns an entry path into a relative path below the extraction directory,
+// rejecting anything absolute or containing `..`.
+fn safe_relative_path(entry: &str, path: &Path) -> Result<PathBuf, ExtractError> {
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(ExtractError::UnsafePath { entry: entry.to_string() })
+            }
+        }
+    }
+    Ok(relative)
+}
+
+// Resolves `target` lexically from `base` (both relative to the extraction
+// directory) and fails if it climbs out of it.
+fn check_link_target(entry: &str, base: &Path, target: &Path) -> Result<(), ExtractError> {
+    let escape = || ExtractError::LinkEscape {
+        entry: entry.to_string(),
+        target: target.display().to_string(),
+    };
+    let mut depth: Vec<_> = base.components().collect();
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth.push(component),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                depth.pop().ok_or_else(escape)?;
+            }
+            Component::RootDir | Component::Prefix(_) => return Err(escape()),
+        }
+    }
+    Ok(())
+}
+
+// An earlier entry may have planted a symlink that a later entry would be
+// written through; lexical checks alone cannot see that.
+fn check_no_symlink_ancestors(entry: &str, root: &Path, relative: &Path) -> Result<(), ExtractError> {
+    let mut current = root.to_path_buf();
+    let mut components = relative.components().peekable();
+    while let Some(component) = components.next() {
+        if components.peek().is_none() {
+            break;
+        }
+        current.push(component);
+        let is_symlink = std::fs::symlink_metadata(&current)
+            .map(|m| m.file_type().is_symlink())
+            .unwrap_or(false);
+        if is_symlink {
+            return Err(ExtractError::ThroughSymlink { entry: entry.to_string() });
+        }
+    }
     Ok(())
 }
@@ -1,27 +1,90 @@
+pub mod cache;
+pub mod cluster;
 pub mod downloader;
 pub mod extractor;
+pub mod logs;
+pub mod platform;
 pub mod process;
+pub mod replset;
+pub mod sharding;
+pub mod tls;
 
 use anyhow::Result;
 use std::path::PathBuf;
+use std::time::Duration;
 use directories::ProjectDirs;
 
-use crate::downloader::{get_download_url, download_file_with_callback, get_os};
-use crate::extractor::extract;
-use crate::process::MongoProcess;
+use crate::cache::{commit_install, install_archive, is_complete_install, staging_dir, InstallLock, InstallMarker};
+use crate::downloader::{get_download_url, Os, check_checksum, default_mirrors, download_and_extract_from_mirrors, download_from_mirrors, fetch_sha256_from_mirrors, get_os, sha256_file, verify_checksum, DownloadEvent, MongoUrl};
+use crate::process::{executable_name, find_binary, find_on_path, make_executable, Readiness};
 
-pub use crate::downloader::DownloadProgress;
+pub use crate::cluster::{ClusterMember, MemberConfig, MongoCluster, ReplicaSetConfig};
+pub use crate::downloader::{ChecksumMismatch, DownloadProgress, ReleaseCatalog, RetryEvent, RetryPolicy};
+pub use crate::sharding::{MongoShardedCluster, ShardedClusterConfig};
+pub use crate::tls::{TlsFiles, TlsOptions};
+pub use crate::logs::{LogEntry, LogOutput, Severity};
+pub use crate::process::{ClusterRole, MongoProcess, MongodConfig, MongosConfig, ShutdownMethod, StartError};
+
+// Fresh ports tried by `set_port_auto` before giving up.
+const AUTO_PORT_ATTEMPTS: u32 = 5;
+
+// How long an automatic port mongod must stay up before a ping counts. One
+// that lost the port to another server exits within this time of starting,
+// possibly after that server already answered the ping.
+const AUTO_PORT_SETTLE: Duration = Duration::from_secs(1);
+
+// "Waiting for connections", logged once mongod accepts clients
+const WAITING_FOR_CONNECTIONS_LOG_ID: u64 = 23016;
+
+// NotYetInitialized, NotPrimaryNoSecondaryOk, NotPrimaryOrSecondary
+const REPLICA_SET_MEMBER_STATE_CODES: [i32; 3] = [94, 13435, 13436];
+
+pub const DEFAULT_STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How `start` decides that mongod is ready.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadinessStrategy {
+    // Connect and list databases every 500 ms
+    #[default]
+    Ping,
+    // Watch the log for "Waiting for connections". Returns as soon as mongod
+    // is listening, but needs the log (any LogOutput except Discard).
+    LogEvent,
+}
+
+// What `start_process` runs.
+#[derive(Debug, Clone)]
+pub(crate) enum Launch {
+    Mongod(Option<ClusterRole>),
+    // The --configdb to route for
+    Mongos(String),
+}
 
 pub enum InitStatus {
     CheckingDB,
     ValidatingInstallation,
     Downloading,
     DownloadProgress(DownloadProgress),
+    DownloadRetry(RetryEvent),
+    VerifyingChecksum,
+    InitiatingReplicaSet,
     SettingUpUser,
     VerifyingCredentials,
     DBInitialized,
 }
 
+/// Where to take MongoDB from when the network must not be used.
+#[derive(Debug, Clone)]
+pub enum OfflineSource {
+    // A MongoDB archive (.tgz or .zip) on local disk
+    Archive(PathBuf),
+    // A directory that already contains the mongod binary
+    Directory(PathBuf),
+    // The mongod found on PATH, or on `MongoEmbedded::search_path` if set
+    SystemPath,
+}
+
+#[derive(Clone)]
 pub struct MongoEmbedded {
     pub version: String,
     pub download_path: PathBuf,
@@ -31,6 +94,21 @@ pub struct MongoEmbedded {
     pub bind_ip: String,
     pub username: Option<String>,
     pub password: Option<String>,
+    pub release_catalog: Option<ReleaseCatalog>,
+    pub sha256: Option<String>,
+    pub verify_checksum: bool,
+    pub retry_policy: RetryPolicy,
+    pub download_mirrors: Vec<String>,
+    pub offline: Option<OfflineSource>,
+    pub search_path: Option<Vec<PathBuf>>,
+    pub extract_binaries: Option<Vec<String>>,
+    pub streaming_extraction: bool,
+    pub log_output: LogOutput,
+    pub readiness: ReadinessStrategy,
+    pub startup_timeout: Duration,
+    pub replica_set: Option<String>,
+    pub key_file: Option<PathBuf>,
+    pub tls: Option<TlsOptions>,
 }
 
 
@@ -51,6 +129,21 @@ impl MongoEmbedded {
             bind_ip: "127.0.0.1".to_string(),
             username: None,
             password: None,
+            release_catalog: None,
+            sha256: None,
+            verify_checksum: true,
+            retry_policy: RetryPolicy::default(),
+            download_mirrors: default_mirrors(),
+            offline: None,
+            search_path: None,
+            extract_binaries: None,
+            streaming_extraction: false,
+            log_output: LogOutput::default(),
+            readiness: ReadinessStrategy::default(),
+            startup_timeout: DEFAULT_STARTUP_TIMEOUT,
+            replica_set: None,
+            key_file: None,
+            tls: None,
         })
     }
 
@@ -59,6 +152,12 @@ impl MongoEmbedded {
         self
     }
 
+    /// Let `start` pick a free port, so parallel tests need not coordinate.
+    /// The chosen port is on [`MongoProcess::port`]. Same as `set_port(0)`.
+    pub fn set_port_auto(self) -> Self {
+        self.set_port(0)
+    }
+
     pub fn set_bind_ip(mut self, bind_ip: &str) -> Self {
         self.bind_ip = bind_ip.to_string();
         self
@@ -75,41 +174,358 @@ impl MongoEmbedded {
         self
     }
 
+    /// Where mongod's log goes. By default the last lines are kept in memory
+    /// and are available from [`MongoProcess::recent_logs`].
+    pub fn set_log_output(mut self, output: LogOutput) -> Self {
+        self.log_output = output;
+        self
+    }
+
+    /// Run mongod as the only member of a replica set called `name`, which
+    /// multi-document transactions and change streams need. The connection
+    /// string then carries `replicaSet=<name>`.
+    pub fn set_replica_set(mut self, name: &str) -> Self {
+        self.replica_set = Some(name.to_string());
+        self
+    }
+
+    /// Keyfile for authentication between replica set members. Without one,
+    /// a replica set with credentials gets a generated `keyfile` in the db
+    /// path.
+    pub fn set_key_file(mut self, path: PathBuf) -> Self {
+        self.key_file = Some(path);
+        self
+    }
+
+    /// Require TLS for every connection. The connection string then carries
+    /// `tls=true` and the CA file, so the driver trusts the server.
+    pub fn set_tls(mut self, options: TlsOptions) -> Self {
+        self.tls = Some(options);
+        self
+    }
+
+    /// Names and addresses the generated server certificate is valid for.
+    fn tls_hosts(&self) -> Vec<String> {
+        let mut hosts: Vec<String> = vec!["localhost".into(), "127.0.0.1".into(), "::1".into()];
+        for ip in self.bind_ip.split(',').map(str::trim) {
+            let wildcard = ip.is_empty() || ip == "0.0.0.0" || ip == "::";
+            if !wildcard && !ip.contains('/') && !hosts.iter().any(|h| h == ip) {
+                hosts.push(ip.to_string());
+            }
+        }
+        hosts
+    }
+
+    // Generates the certificates for TlsOptions::Generated, so every process
+    // started from these settings shares them.
+    pub(crate) fn resolve_tls(&mut self) -> Result<()> {
+        if self.tls == Some(TlsOptions::Generated) {
+            self.tls = Some(TlsOptions::Files(tls::generate(&self.db_path, &self.tls_hosts())?));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn tls_files(&self) -> Result<Option<&TlsFiles>> {
+        match &self.tls {
+            None => Ok(None),
+            Some(TlsOptions::Files(files)) => Ok(Some(files)),
+            Some(TlsOptions::Generated) => Err(anyhow::anyhow!("TLS certificates have not been generated yet")),
+        }
+    }
+
+    pub fn set_readiness_strategy(mut self, strategy: ReadinessStrategy) -> Self {
+        self.readiness = strategy;
+        self
+    }
+
+    /// How long `start` waits for mongod to become ready, 30 seconds by default.
+    pub fn set_startup_timeout(mut self, timeout: Duration) -> Self {
+        self.startup_timeout = timeout;
+        self
+    }
+
+    /// Resolve archives through MongoDB's `full.json` feed instead of
+    /// building URLs locally. Unpublished version/platform combinations then
+    /// fail before anything is downloaded.
+    pub fn set_release_catalog(mut self, catalog: ReleaseCatalog) -> Self {
+        self.release_catalog = Some(catalog);
+        self
+    }
+
+    /// Expected SHA-256 of the archive. Takes precedence over the digest
+    /// from the release catalog or the published `.sha256` file.
+    pub fn set_sha256(mut self, digest: &str) -> Self {
+        self.sha256 = Some(digest.to_string());
+        self
+    }
+
+    pub fn set_checksum_verification(mut self, enabled: bool) -> Self {
+        self.verify_checksum = enabled;
+        self
+    }
+
+    pub fn set_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry_policy = policy;
+        self
+    }
+
+    /// Download archives and checksums from `base_url` instead of
+    /// `https://fastdl.mongodb.org`. `file://` URLs point at a local directory.
+    pub fn set_download_base_url(mut self, base_url: &str) -> Self {
+        self.download_mirrors = vec![base_url.to_string()];
+        self
+    }
+
+    /// Mirrors to try in order, each one only if the previous ones failed.
+    pub fn set_download_mirrors(mut self, mirrors: &[&str]) -> Self {
+        self.download_mirrors = mirrors.iter().map(|m| m.to_string()).collect();
+        self
+    }
+
+    /// Never touch the network: use a local archive, an extracted directory
+    /// or the system mongod instead of downloading.
+    pub fn set_offline(mut self, source: OfflineSource) -> Self {
+        self.offline = Some(source);
+        self
+    }
+
+    /// Directories [`OfflineSource::SystemPath`] searches instead of `PATH`.
+    pub fn set_search_path(mut self, dirs: Vec<PathBuf>) -> Self {
+        self.search_path = Some(dirs);
+        self
+    }
+
+    /// Extract only these binaries (mongod is always included) into a flat
+    /// `bin` directory instead of unpacking the whole archive.
+    pub fn set_selective_extraction(mut self, binaries: &[&str]) -> Self {
+        let mut selected = vec!["mongod".to_string()];
+        for binary in binaries {
+            if !selected.iter().any(|b| b == binary) {
+                selected.push(binary.to_string());
+            }
+        }
+        self.extract_binaries = Some(selected);
+        self
+    }
+
+    /// Unpack tar archives while they download instead of saving them
+    /// first. Nothing is kept in `download_path`, so every fresh install
+    /// downloads again. Zip archives are always saved first.
+    pub fn set_streaming_extraction(mut self, enabled: bool) -> Self {
+        self.streaming_extraction = enabled;
+        self
+    }
+
+    // Executable names that a usable install must contain.
+    fn required_binaries(&self) -> Result<Vec<String>> {
+        let os = get_os()?;
+        let names = match &self.extract_binaries {
+            Some(binaries) => binaries.iter().map(|b| executable_name(b, &os)).collect(),
+            None => vec![executable_name("mongod", &os)],
+        };
+        Ok(names)
+    }
+
+    fn install_marker(&self, archive: String, sha256: String) -> Result<InstallMarker> {
+        Ok(InstallMarker {
+            version: self.version.clone(),
+            archive,
+            sha256,
+            binaries: match &self.extract_binaries {
+                Some(_) => Some(self.required_binaries()?),
+                None => None,
+            },
+        })
+    }
+
+    async fn expected_sha256(&self, mongo_url: &MongoUrl) -> Result<String> {
+        if let Some(digest) = self.sha256.as_ref().or(mongo_url.sha256.as_ref()) {
+            return Ok(digest.clone());
+        }
+        fetch_sha256_from_mirrors(&self.download_mirrors, mongo_url).await
+    }
+
+    fn resolve_download(&self) -> Result<MongoUrl> {
+        match &self.release_catalog {
+            Some(catalog) => catalog.resolve_current(&self.version),
+            None => get_download_url(&self.version),
+        }
+    }
+
     pub fn is_installed(&self) -> bool {
-        let extract_target = self.extract_path.join(self.version.as_str());
-        extract_target.exists()
+        let mongod = match get_os() {
+            Ok(os) => executable_name("mongod", &os),
+            Err(_) => return false,
+        };
+        match &self.offline {
+            // The archive itself is only an input; what runs is its extraction
+            Some(OfflineSource::Archive(_)) | None => match self.required_binaries() {
+                Ok(binaries) => is_complete_install(&self.extract_path.join(self.version.as_str()), &binaries),
+                Err(_) => false,
+            },
+            Some(OfflineSource::Directory(dir)) => find_binary(dir, &mongod).is_some(),
+            Some(OfflineSource::SystemPath) => find_on_path(&mongod, self.search_path.as_deref()).is_some(),
+        }
     }
 
-    pub async fn start(&self) -> Result<MongoProcess> {
-        self.start_with_progress(|_| {}).await
+    fn install_lock_path(&self) -> PathBuf {
+        self.extract_path.join(format!("{}.lock", self.version))
     }
 
-    pub async fn start_with_progress<F>(&self, mut callback: F) -> Result<MongoProcess>
+    // Downloads, verifies and extracts the archive as needed and returns the
+    // directory that contains mongod.
+    async fn install<F>(&self, callback: &mut F) -> Result<PathBuf>
     where
         F: FnMut(InitStatus),
     {
-        callback(InitStatus::CheckingDB);
-        let mongo_url = get_download_url(&self.version)?;
+        let mongo_url = self.resolve_download()?;
         let download_target = self.download_path.join(&mongo_url.filename);
 
+        // Held until we return, so concurrent starters wait for this install
+        // and then find everything in place
+        let _lock = InstallLock::acquire(&self.install_lock_path()).await?;
+
         callback(InitStatus::ValidatingInstallation);
+        let extract_target = self.extract_path.join(self.version.as_str());
+        if is_complete_install(&extract_target, &self.required_binaries()?) {
+            return Ok(extract_target);
+        }
+
+        let streamable = [".tgz", ".tar.gz", ".tar.xz"].iter().any(|ext| mongo_url.filename.ends_with(ext));
+        if self.streaming_extraction && streamable && !download_target.exists() {
+            self.install_streaming(&mongo_url, &extract_target, callback).await?;
+            return Ok(extract_target);
+        }
+
         if !download_target.exists() {
             if !self.download_path.exists() {
                 std::fs::create_dir_all(&self.download_path)?;
             }
             callback(InitStatus::Downloading);
-            download_file_with_callback(&mongo_url.url, &download_target, |progress| {
-                callback(InitStatus::DownloadProgress(progress));
+            download_from_mirrors(&self.download_mirrors, &mongo_url, &download_target, &self.retry_policy, |event| {
+                match event {
+                    DownloadEvent::Progress(progress) => callback(InitStatus::DownloadProgress(progress)),
+                    DownloadEvent::Retrying(retry) => callback(InitStatus::DownloadRetry(retry)),
+                }
             }).await?;
         }
 
-        let extract_target = self.extract_path.join(self.version.as_str());
-        if !extract_target.exists() {
-            extract(&download_target, &extract_target)?;
+        let sha256 = if self.verify_checksum {
+            callback(InitStatus::VerifyingChecksum);
+            let expected = self.expected_sha256(&mongo_url).await?;
+            verify_checksum(&download_target, &expected)?
+        } else {
+            sha256_file(&download_target)?
+        };
+        let marker = self.install_marker(mongo_url.filename.clone(), sha256)?;
+        install_archive(&download_target, &extract_target, &marker)?;
+        Ok(extract_target)
+    }
+
+    // Downloads and unpacks in one pass, checking the digest of the streamed
+    // archive before the install is moved into place.
+    async fn install_streaming<F>(&self, mongo_url: &MongoUrl, extract_target: &std::path::Path, callback: &mut F) -> Result<()>
+    where
+        F: FnMut(InitStatus),
+    {
+        // Fetched first so a missing checksum fails before the download
+        let expected = match self.verify_checksum {
+            true => Some(self.expected_sha256(mongo_url).await?),
+            false => None,
+        };
+        let binaries = match &self.extract_binaries {
+            Some(_) => Some(self.required_binaries()?),
+            None => None,
+        };
+
+        let staging = staging_dir(extract_target)?;
+        callback(InitStatus::Downloading);
+        let streamed = download_and_extract_from_mirrors(&self.download_mirrors, mongo_url, &staging, binaries.as_deref(), &self.retry_policy, |event| {
+            match event {
+                DownloadEvent::Progress(progress) => callback(InitStatus::DownloadProgress(progress)),
+                DownloadEvent::Retrying(retry) => callback(InitStatus::DownloadRetry(retry)),
+            }
+        }).await;
+        let sha256 = match streamed {
+            Ok(sha256) => sha256,
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(&staging);
+                return Err(e);
+            }
+        };
+
+        if let Some(expected) = expected {
+            callback(InitStatus::VerifyingChecksum);
+            if !sha256.eq_ignore_ascii_case(expected.trim()) {
+                let _ = std::fs::remove_dir_all(&staging);
+                return Err(ChecksumMismatch {
+                    // Nothing was saved but what was unpacked there
+                    path: staging.clone(),
+                    expected: expected.trim().to_lowercase(),
+                    actual: sha256,
+                }
+                .into());
+            }
         }
 
-        let os = get_os()?;
-        
+        let marker = self.install_marker(mongo_url.filename.clone(), sha256)?;
+        commit_install(&staging, extract_target, &marker)
+    }
+
+    // Resolves an offline source to the directory (or binary) that holds
+    // mongod, extracting a local archive if needed.
+    async fn prepare_offline<F>(&self, source: &OfflineSource, callback: &mut F) -> Result<PathBuf>
+    where
+        F: FnMut(InitStatus),
+    {
+        let mongod = executable_name("mongod", &get_os()?);
+        callback(InitStatus::ValidatingInstallation);
+        match source {
+            OfflineSource::Archive(archive) => {
+                if !archive.is_file() {
+                    return Err(anyhow::anyhow!("Offline archive {} does not exist", archive.display()));
+                }
+                let extract_target = self.extract_path.join(self.version.as_str());
+                let _lock = InstallLock::acquire(&self.install_lock_path()).await?;
+                if !is_complete_install(&extract_target, &self.required_binaries()?) {
+                    let sha256 = match &self.sha256 {
+                        Some(expected) => {
+                            callback(InitStatus::VerifyingChecksum);
+                            // Never delete an archive the caller provided
+                            check_checksum(archive, expected)?
+                        }
+                        None => sha256_file(archive)?,
+                    };
+                    let marker = self.install_marker(archive.display().to_string(), sha256)?;
+                    install_archive(archive, &extract_target, &marker)?;
+                }
+                Ok(extract_target)
+            }
+            OfflineSource::Directory(dir) => {
+                if find_binary(dir, &mongod).is_none() {
+                    return Err(anyhow::anyhow!("Could not find {} in offline directory {}", mongod, dir.display()));
+                }
+                Ok(dir.clone())
+            }
+            OfflineSource::SystemPath => find_on_path(&mongod, self.search_path.as_deref())
+                .ok_or_else(|| anyhow::anyhow!("Could not find {} on the search path", mongod)),
+        }
+    }
+
+    // The address other members and clients use for this mongod.
+    pub(crate) fn member_host(&self, port: u16) -> String {
+        let ip = self.bind_ip.split(',').next().unwrap_or("127.0.0.1").trim();
+        let ip = match ip {
+            "0.0.0.0" | "" => "127.0.0.1",
+            "::" => "[::1]",
+            ip if ip.contains(':') && !ip.starts_with('[') => return format!("[{}]:{}", ip, port),
+            ip => ip,
+        };
+        format!("{}:{}", ip, port)
+    }
+
+    // Starts mongod on `port` and waits until it is ready.
+    async fn launch(&self, install_root: &std::path::Path, os: &Os, port: u16, launch: &Launch) -> Result<(MongoProcess, mongodb::options::ClientOptions)> {
         // Calculate initial connection string for readiness check
         let uri = if self.bind_ip.contains('/') || self.bind_ip.ends_with(".sock") {
             // Assume unix socket
@@ -119,12 +535,46 @@ impl MongoEmbedded {
             let encoded = self.bind_ip.replace("/", "%2F");
             format!("mongodb://{}/?directConnection=true", encoded)
         } else {
-            format!("mongodb://{}:{}/?directConnection=true", self.bind_ip, self.port)
+            format!("mongodb://{}:{}/?directConnection=true", self.bind_ip, port)
         };
+        let tls = self.tls_files()?;
+        let uri = tls::with_tls(uri, tls);
 
-        // Start process with auth flag if credentials are requested
-        let auth_enabled = self.username.is_some() && self.password.is_some();
-        let mut process = MongoProcess::start(&extract_target, self.port, &self.db_path, &os, &self.bind_ip, auth_enabled, uri.clone())?;
+        let mut process = match launch {
+            Launch::Mongod(cluster_role) => {
+                // Start process with auth flag if credentials are requested
+                let auth_enabled = self.username.is_some() && self.password.is_some();
+                // Members of a set with auth must authenticate to each other
+                let key_file = match &self.key_file {
+                    None if auth_enabled && self.replica_set.is_some() => Some(replset::write_keyfile(&self.db_path)?),
+                    key_file => key_file.clone(),
+                };
+                let config = MongodConfig {
+                    port,
+                    db_path: self.db_path.clone(),
+                    bind_ip: self.bind_ip.clone(),
+                    auth: auth_enabled,
+                    log_output: self.log_output.clone(),
+                    replica_set: self.replica_set.clone(),
+                    cluster_role: *cluster_role,
+                    key_file,
+                    tls: tls.cloned(),
+                };
+                MongoProcess::start(install_root, os, &config, uri.clone())?
+            }
+            Launch::Mongos(config_db) => {
+                let config = MongosConfig {
+                    port,
+                    bind_ip: self.bind_ip.clone(),
+                    config_db: config_db.clone(),
+                    log_output: self.log_output.clone(),
+                    work_dir: self.db_path.clone(),
+                    key_file: self.key_file.clone(),
+                    tls: tls.cloned(),
+                };
+                MongoProcess::start_mongos(install_root, os, &config, uri.clone())?
+            }
+        };
         
         // Need to wait for it to be ready
         // We can try to connect
@@ -132,108 +582,119 @@ impl MongoEmbedded {
         client_options.connect_timeout = Some(std::time::Duration::from_secs(2));
         client_options.server_selection_timeout = Some(std::time::Duration::from_secs(2));
 
-        // Simple loop to wait for readiness
-        let mut connected = false;
-        let start = std::time::Instant::now();
-        println!("DEBUG: Waiting for MongoDB to start at {}", uri);
-        while start.elapsed() < std::time::Duration::from_secs(30) {
-            let client = mongodb::Client::with_options(client_options.clone())?;
-            match client.list_database_names(None, None).await {
-                Ok(_) => {
-                    connected = true;
-                    break;
+        let readiness = match self.readiness {
+            ReadinessStrategy::Ping => {
+                let settle = if self.port == 0 { AUTO_PORT_SETTLE } else { Duration::ZERO };
+                wait_for_ping(&mut process, &client_options, self.startup_timeout, settle).await?
+            }
+            ReadinessStrategy::LogEvent => {
+                process.wait_for_log_id(WAITING_FOR_CONNECTIONS_LOG_ID, self.startup_timeout).await?
+            }
+        };
+
+        if readiness != Readiness::Ready {
+             let error = process.startup_error(readiness, port, &self.db_path, self.startup_timeout).await;
+             process.kill()?;
+             return Err(error.into());
+        }
+        Ok((process, client_options))
+    }
+
+    // Installs or locates mongod and returns the directory it lives in.
+    pub(crate) async fn prepare<F>(&self, callback: &mut F) -> Result<PathBuf>
+    where
+        F: FnMut(InitStatus),
+    {
+        let install_root = match &self.offline {
+            Some(source) => self.prepare_offline(source, callback).await?,
+            None => self.install(callback).await?,
+        };
+        // Only binaries we extracted are ours to change
+        if install_root.starts_with(&self.extract_path) {
+            for binary in self.required_binaries()? {
+                if let Some(path) = find_binary(&install_root, &binary) {
+                    make_executable(&path)?;
                 }
-                Err(e) => {
-                    println!("DEBUG: Connection attempt failed: {:?}", e);
-                    // If unauthorized error, it means we are connected but need auth, which is fine for readiness check
-                    // "Unauthorized" usually is error code 13
-                    match *e.kind {
-                         mongodb::error::ErrorKind::Command(ref cmd_err) => {
-                             if cmd_err.code == 51 || cmd_err.code == 13 || cmd_err.code == 18 { // 51: UserAlreadyExists?, 13: Unauthorized, 18: AuthFailed
-                                 connected = true;
-                                 break;
-                             }
-                         },
-                         _ => {}
-                    }
+            }
+        }
+        Ok(install_root)
+    }
+
+    // Starts mongod and waits until it is ready, picking another port when
+    // an automatic one turns out to be taken.
+    pub(crate) async fn start_process(&self, install_root: &std::path::Path, os: &Os, launch: &Launch) -> Result<(MongoProcess, mongodb::options::ClientOptions)> {
+        if self.readiness == ReadinessStrategy::LogEvent && matches!(self.log_output, LogOutput::Discard) {
+            return Err(anyhow::anyhow!("ReadinessStrategy::LogEvent needs the mongod log, but it is discarded"));
+        }
+
+        // With an automatic port another process can grab the port between
+        // us picking it and mongod binding it; try again with a new one
+        let mut attempt = 1;
+        loop {
+            let port = match self.port {
+                0 => free_port()?,
+                port => port,
+            };
+            match self.launch(install_root, os, port, launch).await {
+                Ok(launched) => return Ok(launched),
+                Err(e) if self.port == 0 && attempt < AUTO_PORT_ATTEMPTS && is_address_in_use(&e) => {
+                    attempt += 1;
                 }
+                Err(e) => return Err(e),
             }
-             
-            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
         }
+    }
 
-        if !connected {
-             println!("DEBUG: Timed out waiting for start.");
-             process.kill()?;
-             return Err(anyhow::anyhow!("Timed out waiting for MongoDB to start"));
+    pub async fn start(&self) -> Result<MongoProcess> {
+        self.start_with_progress(|_| {}).await
+    }
+
+    pub async fn start_with_progress<F>(&self, mut callback: F) -> Result<MongoProcess>
+    where
+        F: FnMut(InitStatus),
+    {
+        if self.tls == Some(TlsOptions::Generated) {
+            let mut resolved = self.clone();
+            resolved.resolve_tls()?;
+            return Box::pin(resolved.start_with_progress(callback)).await;
+        }
+
+        callback(InitStatus::CheckingDB);
+        if self.replica_set.is_some() {
+            cluster::check_settings(self)?;
+        }
+
+        let install_root = self.prepare(&mut callback).await?;
+        let os = get_os()?;
+        let (mut process, client_options) = self.start_process(&install_root, &os, &Launch::Mongod(None)).await?;
+        let port = process.port;
+
+        if let Some(name) = &self.replica_set {
+            callback(InitStatus::InitiatingReplicaSet);
+            let host = self.member_host(port);
+            let client = mongodb::Client::with_options(client_options.clone())?;
+            replset::initiate(&client, replset::single_node_config(name, &host)).await?;
+            replset::wait_for_primary(&client, self.startup_timeout).await?;
+            process.connection_string = tls::with_tls(replset::connection_string(&[host], name, None), self.tls_files()?);
         }
 
         if let (Some(username), Some(password)) = (&self.username, &self.password) {
-             callback(InitStatus::SettingUpUser);
-             let client = mongodb::Client::with_options(client_options.clone())?;
-
-             // Try to create user. This only works if localhost exception is active (no users)
-             use mongodb::bson::doc;
-             let db = client.database("admin");
-             let run_cmd = db.run_command(doc! {
-                "createUser": username,
-                "pwd": password,
-                "roles": [
-                    { "role": "root", "db": "admin" }
-                ]
-             }, None).await;
-
-             match run_cmd {
-                Ok(_) => {
-                    // Created user successfully
-                },
-                Err(e) => {
-                     // Check if error is unauthorized or "already exists"
-                     let kind = &*e.kind;
-                     let needs_verify;
-                     if let mongodb::error::ErrorKind::Command(cmd_err) = kind {
-                         if cmd_err.code == 51 { // UserAlreadyExists
-                             needs_verify = true;
-                         } else if cmd_err.code == 13 { // Unauthorized
-                             needs_verify = true;
-                         } else {
-                             // Unexpected error, maybe fail or try verify
-                             needs_verify = true; 
-                         }
-                     } else {
-                         needs_verify = true; // Connection error or other?
-                     }
-
-                     if needs_verify {
-                         callback(InitStatus::VerifyingCredentials);
-                         // Try to authenticate
-                         let mut auth_opts = client_options.clone();
-                         auth_opts.credential = Some(mongodb::options::Credential::builder()
-                            .username(username.clone())
-                            .password(password.clone())
-                            .source("admin".to_string())
-                            .build());
-                         
-                         let auth_client = mongodb::Client::with_options(auth_opts)?;
-                         // Verify by running a command that requires auth
-                         if let Err(auth_err) = auth_client.database("admin").run_command(doc! { "ping": 1 }, None).await {
-                             process.kill()?;
-                             return Err(anyhow::anyhow!("Authentication failed or invalid credentials provided: {}", auth_err));
-                         }
-                     }
-                }
+             if let Err(e) = setup_user(&client_options, username, password, &mut callback).await {
+                 process.kill()?;
+                 return Err(e);
              }
 
              // Update connection string to include credentials
-             let final_uri;
-             if self.bind_ip.contains('/') || self.bind_ip.ends_with(".sock") {
+             let final_uri = if let Some(name) = &self.replica_set {
+                 replset::connection_string(&[self.member_host(port)], name, Some((username, password)))
+             } else if self.bind_ip.contains('/') || self.bind_ip.ends_with(".sock") {
                  let encoded = self.bind_ip.replace("/", "%2F");
                  // For sockets, credentials go in the beginning
-                 final_uri = format!("mongodb://{}:{}@{}", username, password, encoded);
+                 format!("mongodb://{}:{}@{}", username, password, encoded)
              } else {
-                 final_uri = format!("mongodb://{}:{}@{}:{}/", username, password, self.bind_ip, self.port);
-             }
-             process.connection_string = final_uri;
+                 format!("mongodb://{}:{}@{}:{}/", username, password, self.bind_ip, port)
+             };
+             process.connection_string = tls::with_tls(final_uri, self.tls_files()?);
         }
 
         callback(InitStatus::DBInitialized);
@@ -241,3 +702,124 @@ impl MongoEmbedded {
     }
 }
 
+// Creates the root user through the localhost exception, or checks that the
+// credentials work when users already exist.
+pub(crate) async fn setup_user<F>(
+    client_options: &mongodb::options::ClientOptions,
+    username: &str,
+    password: &str,
+    callback: &mut F,
+) -> Result<()>
+where
+    F: FnMut(InitStatus),
+{
+    callback(InitStatus::SettingUpUser);
+    let client = mongodb::Client::with_options(client_options.clone())?;
+
+    // Try to create user. This only works if localhost exception is active (no users)
+    use mongodb::bson::doc;
+    let db = client.database("admin");
+    let run_cmd = db.run_command(doc! {
+        "createUser": username,
+        "pwd": password,
+        "roles": [
+            { "role": "root", "db": "admin" }
+        ]
+    }, None).await;
+
+    match run_cmd {
+        Ok(_) => {
+            // Created user successfully
+        },
+        Err(e) => {
+            // Check if error is unauthorized or "already exists"
+            let kind = &*e.kind;
+            let needs_verify;
+            if let mongodb::error::ErrorKind::Command(cmd_err) = kind {
+                if cmd_err.code == 51 { // UserAlreadyExists
+                    needs_verify = true;
+                } else if cmd_err.code == 13 { // Unauthorized
+                    needs_verify = true;
+                } else {
+                    // Unexpected error, maybe fail or try verify
+                    needs_verify = true; 
+                }
+            } else {
+                needs_verify = true; // Connection error or other?
+            }
+
+            if needs_verify {
+                callback(InitStatus::VerifyingCredentials);
+                // Try to authenticate
+                let mut auth_opts = client_options.clone();
+                auth_opts.credential = Some(mongodb::options::Credential::builder()
+                    .username(username.to_string())
+                    .password(password.to_string())
+                    .source("admin".to_string())
+                    .build());
+                
+                let auth_client = mongodb::Client::with_options(auth_opts)?;
+                // Verify by running a command that requires auth
+                if let Err(auth_err) = auth_client.database("admin").run_command(doc! { "ping": 1 }, None).await {
+                    return Err(anyhow::anyhow!("Authentication failed or invalid credentials provided: {}", auth_err));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Polls the server until it answers. Auth errors count as ready: mongod is
+// up, we just have no user yet.
+// Pings until the server answers. Whoever answers may not be our mongod,
+// so the answer only counts if it is still running `settle` after start.
+async fn wait_for_ping(
+    process: &mut MongoProcess,
+    client_options: &mongodb::options::ClientOptions,
+    timeout: Duration,
+    settle: Duration,
+) -> Result<Readiness> {
+    let start = std::time::Instant::now();
+    while start.elapsed() < timeout {
+        if let Some(status) = process.exit_status()? {
+            return Ok(Readiness::Exited(status));
+        }
+
+        let client = mongodb::Client::with_options(client_options.clone())?;
+        let answered = match client.list_database_names(None, None).await {
+            Ok(_) => true,
+            Err(e) => match *e.kind {
+                // Connected but needs auth, which is fine for readiness.
+                // 51: UserAlreadyExists?, 13: Unauthorized, 18: AuthFailed
+                mongodb::error::ErrorKind::Command(ref cmd_err) if [51, 13, 18].contains(&cmd_err.code) => true,
+                // Replica set members that are not initiated yet, or
+                // arbiters, answer but refuse to list databases
+                mongodb::error::ErrorKind::Command(ref cmd_err) => REPLICA_SET_MEMBER_STATE_CODES.contains(&cmd_err.code),
+                _ => false,
+            },
+        };
+        if answered {
+            if let Some(remaining) = settle.checked_sub(start.elapsed()) {
+                tokio::time::sleep(remaining).await;
+            }
+            return Ok(match process.exit_status()? {
+                Some(status) => Readiness::Exited(status),
+                None => Readiness::Ready,
+            });
+        }
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    Ok(Readiness::TimedOut)
+}
+
+// Asks the OS for a port nobody is listening on. It is free again as soon as
+// the listener is dropped, so mongod can still lose a race for it.
+fn free_port() -> Result<u16> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    Ok(listener.local_addr()?.port())
+}
+
+fn is_address_in_use(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref::<StartError>(), Some(StartError::AddressInUse { .. }))
+}
@@ -0,0 +1,279 @@
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// Lines kept in memory by [`LogOutput::Buffer`] and [`LogOutput::Callback`].
+pub const DEFAULT_LOG_LINES: usize = 1000;
+
+// Parsed entries a slow subscriber may fall behind by before it lags.
+pub(crate) const LOG_EVENT_CAPACITY: usize = 1024;
+
+// Only the end of a log file is read back for `recent_logs`.
+const FILE_TAIL_BYTES: u64 = 256 * 1024;
+
+// How often a followed output file is checked for new lines.
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Receives each line mongod logs along with its parsed form, `None` for
+/// lines that are not structured log entries.
+pub type LogCallback = Arc<dyn Fn(&str, Option<&LogEntry>) + Send + Sync>;
+
+/// Where mongod's log goes.
+#[derive(Clone)]
+pub enum LogOutput {
+    // Nothing is kept, as with `--quiet --logpath /dev/null`
+    Discard,
+    // The last N lines are kept in memory
+    Buffer(usize),
+    // `mongod.log` inside the db path
+    DbPathFile,
+    // A log file of your choosing, appended to across restarts
+    File(PathBuf),
+    // Every line and its parsed entry are passed to the callback as they
+    // arrive; the last DEFAULT_LOG_LINES are kept in memory as well
+    Callback(LogCallback),
+}
+
+impl Default for LogOutput {
+    fn default() -> Self {
+        LogOutput::Buffer(DEFAULT_LOG_LINES)
+    }
+}
+
+impl fmt::Debug for LogOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogOutput::Discard => write!(f, "Discard"),
+            LogOutput::Buffer(lines) => write!(f, "Buffer({})", lines),
+            LogOutput::DbPathFile => write!(f, "DbPathFile"),
+            LogOutput::File(path) => write!(f, "File({:?})", path),
+            LogOutput::Callback(_) => write!(f, "Callback(..)"),
+        }
+    }
+}
+
+impl LogOutput {
+    // The file mongod should write to itself, if any.
+    pub(crate) fn log_path(&self, db_path: &Path) -> Option<PathBuf> {
+        match self {
+            LogOutput::DbPathFile => Some(db_path.join("mongod.log")),
+            LogOutput::File(path) => Some(path.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Log severity, the `s` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Fatal,
+    Error,
+    Warning,
+    Info,
+    // Debug level 1 to 5
+    Debug(u8),
+}
+
+impl Severity {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "F" => Some(Severity::Fatal),
+            "E" => Some(Severity::Error),
+            "W" => Some(Severity::Warning),
+            "I" => Some(Severity::Info),
+            _ => s.strip_prefix('D').and_then(|level| level.parse().ok()).map(Severity::Debug),
+        }
+    }
+}
+
+/// One line of mongod's structured log (MongoDB 4.4 and later).
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogEntry {
+    // `t`, ISO 8601 as logged
+    pub timestamp: String,
+    // `s`
+    pub severity: Severity,
+    // `c`, e.g. NETWORK, STORAGE, COMMAND, INDEX
+    pub component: String,
+    // `id`, stable across releases, e.g. 23016 "Waiting for connections"
+    pub id: u64,
+    // `ctx`, the thread or connection that logged it
+    pub context: String,
+    // `msg`
+    pub message: String,
+    // `attr`, `Null` if absent
+    pub attr: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct RawEntry {
+    t: RawTimestamp,
+    s: String,
+    c: String,
+    id: u64,
+    ctx: String,
+    msg: String,
+    #[serde(default)]
+    attr: serde_json::Value,
+}
+
+#[derive(Deserialize)]
+struct RawTimestamp {
+    #[serde(rename = "$date")]
+    date: String,
+}
+
+impl LogEntry {
+    /// `None` for anything that is not a structured log line, e.g. output
+    /// printed before logging is set up.
+    pub fn parse(line: &str) -> Option<Self> {
+        let raw: RawEntry = serde_json::from_str(line).ok()?;
+        Some(LogEntry {
+            timestamp: raw.t.date,
+            severity: Severity::parse(&raw.s)?,
+            component: raw.c,
+            id: raw.id,
+            context: raw.ctx,
+            message: raw.msg,
+            attr: raw.attr,
+        })
+    }
+
+    /// A single attribute, e.g. `durationMillis` of a slow query.
+    pub fn attr(&self, key: &str) -> Option<&serde_json::Value> {
+        self.attr.get(key)
+    }
+}
+
+/// Read access to the recent log of a running mongod.
+#[derive(Clone)]
+pub(crate) enum LogTail {
+    None,
+    Memory(Arc<Follower>),
+    File(PathBuf),
+}
+
+// The lines read so far from a file mongod writes its output to.
+pub(crate) struct Follower {
+    lines: Mutex<VecDeque<String>>,
+    // Asks the reader to stop once it has read to the end of the file
+    stop: AtomicBool,
+    // Set once the reader has stopped and every line has been read
+    closed: AtomicBool,
+}
+
+impl LogTail {
+    pub(crate) fn recent(&self, lines: usize) -> Vec<String> {
+        match self {
+            LogTail::None => Vec::new(),
+            LogTail::Memory(follower) => {
+                let buffer = follower.lines.lock().unwrap();
+                buffer.iter().skip(buffer.len().saturating_sub(lines)).cloned().collect()
+            }
+            LogTail::File(path) => tail_file(path, lines).unwrap_or_default(),
+        }
+    }
+
+    /// After mongod exited its last lines may not have been read yet; waits
+    /// up to `timeout` for them.
+    pub(crate) async fn wait_for_end(&self, timeout: Duration) {
+        if let LogTail::Memory(follower) = self {
+            follower.stop.store(true, Ordering::Release);
+            let deadline = tokio::time::Instant::now() + timeout;
+            while !follower.closed.load(Ordering::Acquire) && tokio::time::Instant::now() < deadline {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        }
+    }
+
+    /// Stops reading once the lines written so far are in, e.g. when mongod
+    /// is detached and keeps writing after we are gone.
+    pub(crate) fn stop(&self) {
+        if let LogTail::Memory(follower) = self {
+            follower.stop.store(true, Ordering::Release);
+        }
+    }
+}
+
+/// Follows the file at `path` that mongod writes its output to on a
+/// background thread, keeping the last `capacity` lines, handing each one to
+/// `callback` and publishing the parsed entries on `events`. Reading ends
+/// after [`LogTail::stop`] or once every `LogTail` for it is dropped.
+pub(crate) fn follow(
+    path: &Path,
+    capacity: usize,
+    callback: Option<LogCallback>,
+    events: Option<broadcast::Sender<LogEntry>>,
+) -> std::io::Result<LogTail> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+    let follower = Arc::new(Follower {
+        lines: Mutex::new(VecDeque::with_capacity(capacity.min(DEFAULT_LOG_LINES))),
+        stop: AtomicBool::new(false),
+        closed: AtomicBool::new(false),
+    });
+    let reader_follower = follower.clone();
+    std::thread::spawn(move || {
+        let follower = reader_follower;
+        let mut pending = Vec::new();
+        loop {
+            // Checked before reading so the end of the file is read once more
+            let stopping = follower.stop.load(Ordering::Acquire) || Arc::strong_count(&follower) == 1;
+            if reader.read_until(b'\n', &mut pending).is_err() {
+                break;
+            }
+            if !pending.ends_with(b"\n") {
+                if !stopping {
+                    // A partial line stays pending until mongod finishes it
+                    std::thread::sleep(FOLLOW_POLL_INTERVAL);
+                    continue;
+                }
+                if pending.is_empty() {
+                    break;
+                }
+            }
+
+            let line = String::from_utf8_lossy(&pending).trim_end_matches(['\n', '\r']).to_string();
+            pending.clear();
+            let events = events.as_ref().filter(|e| e.receiver_count() > 0);
+            let entry = if callback.is_some() || events.is_some() { LogEntry::parse(&line) } else { None };
+            if let Some(callback) = &callback {
+                callback(&line, entry.as_ref());
+            }
+            if let (Some(events), Some(entry)) = (events, entry) {
+                // Fails only when every receiver is gone
+                let _ = events.send(entry);
+            }
+            let mut lines = follower.lines.lock().unwrap();
+            if lines.len() == capacity {
+                lines.pop_front();
+            }
+            if capacity > 0 {
+                lines.push_back(line);
+            }
+        }
+        follower.closed.store(true, Ordering::Release);
+    });
+    Ok(LogTail::Memory(follower))
+}
+
+fn tail_file(path: &Path, lines: usize) -> std::io::Result<Vec<String>> {
+    let mut file = std::fs::File::open(path)?;
+    let start = file.metadata()?.len().saturating_sub(FILE_TAIL_BYTES);
+    file.seek(SeekFrom::Start(start))?;
+
+    let mut contents = Vec::new();
+    file.read_to_end(&mut contents)?;
+    let contents = String::from_utf8_lossy(&contents);
+    let mut all: Vec<&str> = contents.lines().collect();
+    // Drop what is probably a partial first line
+    if start > 0 && !all.is_empty() {
+        all.remove(0);
+    }
+    Ok(all[all.len().saturating_sub(lines)..].iter().map(|l| l.to_string()).collect())
+}
@@ -0,0 +1,200 @@
+use anyhow::{anyhow, Context, Result};
+use std::path::Path;
+use crate::downloader::Arch;
+
+// Target used when the distribution cannot be detected or is unknown to us.
+// This was the only target before distro detection existed.
+pub const DEFAULT_LINUX_TARGET: &str = "ubuntu2204";
+
+/// A MongoDB Linux build target, e.g. `ubuntu2204` or `rhel90`.
+struct TargetSpec {
+    name: &'static str,
+    arches: &'static [Arch],
+    // First version that was published for this target.
+    since: (u32, u32, u32),
+    // First version that was no longer published, if the target was dropped.
+    until: Option<(u32, u32, u32)>,
+}
+
+const BOTH: &[Arch] = &[Arch::X86_64, Arch::Aarch64];
+const X86_ONLY: &[Arch] = &[Arch::X86_64];
+const ARM_ONLY: &[Arch] = &[Arch::Aarch64];
+
+const TARGETS: &[TargetSpec] = &[
+    TargetSpec { name: "ubuntu2404", arches: BOTH, since: (8, 0, 0), until: None },
+    TargetSpec { name: "ubuntu2204", arches: BOTH, since: (6, 0, 4), until: None },
+    TargetSpec { name: "ubuntu2004", arches: BOTH, since: (4, 4, 0), until: None },
+    TargetSpec { name: "ubuntu1804", arches: BOTH, since: (4, 0, 1), until: Some((7, 0, 0)) },
+    TargetSpec { name: "debian12", arches: X86_ONLY, since: (7, 0, 3), until: None },
+    TargetSpec { name: "debian11", arches: X86_ONLY, since: (5, 0, 8), until: None },
+    TargetSpec { name: "debian10", arches: X86_ONLY, since: (4, 2, 1), until: Some((7, 0, 0)) },
+    TargetSpec { name: "rhel90", arches: BOTH, since: (6, 0, 4), until: None },
+    TargetSpec { name: "rhel82", arches: ARM_ONLY, since: (4, 4, 4), until: None },
+    TargetSpec { name: "rhel80", arches: X86_ONLY, since: (4, 2, 1), until: None },
+    TargetSpec { name: "rhel70", arches: X86_ONLY, since: (3, 2, 0), until: Some((8, 0, 0)) },
+    TargetSpec { name: "amazon2023", arches: BOTH, since: (7, 0, 0), until: None },
+    TargetSpec { name: "amazon2", arches: BOTH, since: (4, 4, 0), until: None },
+    TargetSpec { name: "suse15", arches: X86_ONLY, since: (4, 2, 1), until: None },
+];
+
+/// Contents of `/etc/os-release` that matter for picking a MongoDB build.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinuxDistro {
+    pub id: String,
+    pub id_like: Vec<String>,
+    pub version_id: String,
+    pub ubuntu_codename: Option<String>,
+}
+
+impl LinuxDistro {
+    /// Detects the distribution of the running system.
+    pub fn detect() -> Result<Self> {
+        Self::detect_in(Path::new("/"))
+    }
+
+    /// Detects the distribution of a system rooted at `root`, reading
+    /// `etc/os-release` or, failing that, `usr/lib/os-release`.
+    pub fn detect_in(root: &Path) -> Result<Self> {
+        let candidates = [root.join("etc/os-release"), root.join("usr/lib/os-release")];
+        for path in &candidates {
+            if path.is_file() {
+                let contents = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?;
+                return Ok(Self::parse(&contents));
+            }
+        }
+        Err(anyhow!("No os-release file found under {}", root.display()))
+    }
+
+    pub fn parse(contents: &str) -> Self {
+        let mut distro = Self::default();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim().trim_matches('"').trim_matches('\'').to_string();
+            match key.trim() {
+                "ID" => distro.id = value.to_lowercase(),
+                "ID_LIKE" => {
+                    distro.id_like = value.split_whitespace().map(|s| s.to_lowercase()).collect()
+                }
+                "VERSION_ID" => distro.version_id = value,
+                "UBUNTU_CODENAME" => distro.ubuntu_codename = Some(value.to_lowercase()),
+                _ => {}
+            }
+        }
+        distro
+    }
+
+    fn is_like(&self, id: &str) -> bool {
+        self.id == id || self.id_like.iter().any(|like| like == id)
+    }
+
+    fn major_version(&self) -> Option<u32> {
+        self.version_id.split('.').next()?.parse().ok()
+    }
+
+    /// MongoDB targets usable on this distribution, most preferred first.
+    /// Later entries are binary compatible fallbacks for releases that did
+    /// not publish the exact target.
+    pub fn candidate_targets(&self, arch: &Arch) -> Vec<&'static str> {
+        let chain: &[&'static str] = match self.id.as_str() {
+            "ubuntu" => ubuntu_chain(self.major_version()),
+            "debian" => match self.major_version() {
+                Some(v) if v >= 12 => &["debian12", "ubuntu2204"],
+                Some(11) => &["debian11", "ubuntu2004"],
+                Some(10) => &["debian10"],
+                _ => &["debian12", "ubuntu2204"],
+            },
+            "amzn" => match self.major_version() {
+                Some(2) => &["amazon2", "rhel70"],
+                _ => &["amazon2023", "rhel90"],
+            },
+            "sles" | "opensuse-leap" => &["suse15"],
+            "fedora" => &["rhel90", "rhel82", "rhel80"],
+            _ if self.is_like("rhel") || self.is_like("centos") || self.is_like("fedora") => {
+                match self.major_version() {
+                    Some(7) => &["rhel70"],
+                    Some(8) => &["rhel82", "rhel80"],
+                    _ => &["rhel90", "rhel82", "rhel80"],
+                }
+            }
+            // Derivatives such as Linux Mint or Pop!_OS report their own
+            // VERSION_ID but keep the codename of the Ubuntu base.
+            _ if self.is_like("ubuntu") => match self.ubuntu_codename.as_deref() {
+                Some("noble") => ubuntu_chain(Some(24)),
+                Some("jammy") => ubuntu_chain(Some(22)),
+                Some("focal") => ubuntu_chain(Some(20)),
+                Some("bionic") => ubuntu_chain(Some(18)),
+                _ => &[DEFAULT_LINUX_TARGET],
+            },
+            _ if self.is_like("debian") => &["debian12", "ubuntu2204"],
+            _ if self.is_like("suse") => &["suse15"],
+            _ => &[DEFAULT_LINUX_TARGET],
+        };
+
+        chain
+            .iter()
+            .copied()
+            .filter(|name| target_spec(name).is_some_and(|spec| spec.arches.contains(arch)))
+            .collect()
+    }
+
+    /// Picks the first candidate target that MongoDB published for `version`.
+    pub fn select_target(&self, arch: &Arch, version: &str) -> Result<&'static str> {
+        let candidates = self.candidate_targets(arch);
+        let parsed = parse_version(version)?;
+        candidates
+            .iter()
+            .copied()
+            .find(|name| target_spec(name).is_some_and(|spec| spec.publishes(parsed)))
+            .ok_or_else(|| {
+                anyhow!(
+                    "No MongoDB {} build is published for {} {} ({:?}); tried targets: {}",
+                    version,
+                    self.id,
+                    self.version_id,
+                    arch,
+                    candidates.join(", ")
+                )
+            })
+    }
+}
+
+impl TargetSpec {
+    fn publishes(&self, version: (u32, u32, u32)) -> bool {
+        version >= self.since && self.until.is_none_or(|until| version < until)
+    }
+}
+
+fn ubuntu_chain(major: Option<u32>) -> &'static [&'static str] {
+    match major {
+        Some(v) if v >= 24 => &["ubuntu2404", "ubuntu2204"],
+        Some(22) | Some(23) => &["ubuntu2204"],
+        Some(20) | Some(21) => &["ubuntu2004", "ubuntu1804"],
+        Some(18) | Some(19) => &["ubuntu1804"],
+        _ => &[DEFAULT_LINUX_TARGET],
+    }
+}
+
+fn target_spec(name: &str) -> Option<&'static TargetSpec> {
+    TARGETS.iter().find(|spec| spec.name == name)
+}
+
+/// Parses `major.minor.patch`, ignoring pre-release suffixes like `-rc1`.
+pub(crate) fn parse_version(version: &str) -> Result<(u32, u32, u32)> {
+    let core = version.split(['-', '+']).next().unwrap_or(version);
+    let mut parts = core.split('.').map(|p| p.parse::<u32>());
+    let mut next = || -> Result<u32> {
+        match parts.next() {
+            Some(Ok(n)) => Ok(n),
+            Some(Err(_)) => Err(anyhow!("Invalid MongoDB version: {}", version)),
+            None => Ok(0),
+        }
+    };
+    Ok((next()?, next()?, next()?))
+}
@@ -1,80 +1,664 @@
 use anyhow::{anyhow, Result};
-use std::process::{Child, Command};
+use std::fmt;
+use std::process::{Child, Command, ExitStatus, Stdio};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use crate::downloader::Os;
+use crate::tls::TlsFiles;
+use crate::logs::{follow, LogEntry, LogOutput, LogTail, DEFAULT_LOG_LINES, LOG_EVENT_CAPACITY};
+use tokio::sync::broadcast;
 
+// Lines of stderr kept for error reports.
+const STDERR_LINES: usize = 100;
+
+// Log lines included in a StartError.
+const STARTUP_ERROR_LOG_LINES: usize = 20;
+
+// mongod exit codes, see src/mongo/util/exit_code.h
+const EXIT_NET_ERROR: i32 = 48;
+const EXIT_NEED_DOWNGRADE: i32 = 62;
+
+// How often startup checks whether mongod has exited.
+const READINESS_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// How long a dropped mongod gets to shut down cleanly before it is killed.
+const DROP_GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// How [`MongoProcess::shutdown`] stopped mongod.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShutdownMethod {
+    // The admin `shutdown` command
+    Command,
+    // SIGTERM, after the command failed or timed out
+    Signal,
+    // Killed as a last resort
+    Killed,
+    // mongod was not running any more
+    AlreadyExited,
+}
+
+/// Outcome of waiting for mongod to accept connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Readiness {
+    Ready,
+    TimedOut,
+    Exited(ExitStatus),
+}
+
+/// Why `start` could not bring mongod up. Each variant carries the last
+/// lines of its log and stderr.
+#[derive(Debug)]
+pub enum StartError {
+    // Another process is listening on the port
+    AddressInUse { port: u16, logs: Vec<String> },
+    // Another mongod is using the db path
+    DbPathLocked { db_path: PathBuf, logs: Vec<String> },
+    // The data files were written by an incompatible MongoDB version
+    FcvMismatch { logs: Vec<String> },
+    // The build needs CPU features this machine lacks, e.g. AVX for 5.0+
+    UnsupportedCpu { status: ExitStatus, logs: Vec<String> },
+    // Any other exit during startup
+    Exited { status: ExitStatus, logs: Vec<String> },
+    // Still not ready when the startup timeout ran out
+    Timeout { timeout: Duration, logs: Vec<String> },
+}
+
+impl StartError {
+    pub fn logs(&self) -> &[String] {
+        match self {
+            StartError::AddressInUse { logs, .. }
+            | StartError::DbPathLocked { logs, .. }
+            | StartError::FcvMismatch { logs }
+            | StartError::UnsupportedCpu { logs, .. }
+            | StartError::Exited { logs, .. }
+            | StartError::Timeout { logs, .. } => logs,
+        }
+    }
+}
+
+impl fmt::Display for StartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartError::AddressInUse { port, .. } => write!(f, "Port {} is already in use", port)?,
+            StartError::DbPathLocked { db_path, .. } => {
+                write!(f, "Database path {} is locked by another mongod", db_path.display())?
+            }
+            StartError::FcvMismatch { .. } => write!(
+                f,
+                "The data files need a featureCompatibilityVersion this mongod does not support; \
+                 use the MongoDB version that wrote them or start with an empty db path"
+            )?,
+            StartError::UnsupportedCpu { status, .. } => {
+                write!(f, "mongod exited with {}: this CPU cannot run this MongoDB build", status)?
+            }
+            StartError::Exited { status, .. } => write!(f, "mongod exited with {} before it was ready", status)?,
+            StartError::Timeout { .. } => write!(f, "Timed out waiting for MongoDB to start")?,
+        }
+        if !self.logs().is_empty() {
+            write!(f, ". Last log lines:\n{}", self.logs().join("\n"))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for StartError {}
+
+fn killed_by_sigill(status: &ExitStatus) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal() == Some(libc::SIGILL)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = status;
+        false
+    }
+}
+
+/// A running mongod. Dropping it stops the server: it is asked to shut down
+/// cleanly and killed if it has not exited within 10 seconds. The drop
+/// blocks the calling thread until then, also inside an async runtime; call
+/// [`MongoProcess::shutdown`] to stop it without blocking. Use
+/// [`MongoProcess::detach`] to leave it running.
 pub struct MongoProcess {
-    child: Child,
+    // `None` once the process has been killed or detached
+    child: Option<Child>,
+    logs: LogTail,
+    stderr: LogTail,
+    log_events: broadcast::Sender<LogEntry>,
     pub connection_string: String,
+    // The port mongod listens on, also when it was picked automatically
+    pub port: u16,
 }
 
-impl MongoProcess {
-    pub fn start(
-        extracted_path: &Path,
-        port: u16,
-        db_path: &Path,
-        os: &Os,
-        bind_ip: &str,
-        auth: bool,
-        connection_string: String,
-    ) -> Result<Self> {
-        let binary_name = match os {
-            Os::Windows => "mongod.exe",
-            _ => "mongod",
-        };
+/// How to launch a single mongod.
+#[derive(Debug, Clone)]
+pub struct MongodConfig {
+    pub port: u16,
+    pub db_path: PathBuf,
+    pub bind_ip: String,
+    pub auth: bool,
+    pub log_output: LogOutput,
+    // Passed as --replSet
+    pub replica_set: Option<String>,
+    pub cluster_role: Option<ClusterRole>,
+    // Passed as --keyFile, for authentication between members
+    pub key_file: Option<PathBuf>,
+    // Requires TLS for every connection
+    pub tls: Option<TlsFiles>,
+}
 
-        let binary_path = find_binary(extracted_path, binary_name)
-            .ok_or_else(|| anyhow!("Could not find {} in extracted directory", binary_name))?;
+impl MongodConfig {
+    pub fn new(port: u16, db_path: &Path) -> Self {
+        Self {
+            port,
+            db_path: db_path.to_path_buf(),
+            bind_ip: "127.0.0.1".to_string(),
+            auth: false,
+            log_output: LogOutput::default(),
+            replica_set: None,
+            cluster_role: None,
+            key_file: None,
+            tls: None,
+        }
+    }
+}
 
-        // Ensure binary is executable on Unix
-        #[cfg(unix)]
-        {
-            use std::os::unix::fs::PermissionsExt;
-            let mut perms = std::fs::metadata(&binary_path)?.permissions();
-            perms.set_mode(0o755);
-            std::fs::set_permissions(&binary_path, perms)?;
+/// The part a mongod plays in a sharded cluster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClusterRole {
+    // --configsvr
+    ConfigServer,
+    // --shardsvr
+    ShardServer,
+}
+
+/// How to launch a mongos router.
+#[derive(Debug, Clone)]
+pub struct MongosConfig {
+    pub port: u16,
+    pub bind_ip: String,
+    // `<config replica set>/<host>,<host>`, passed as --configdb
+    pub config_db: String,
+    pub log_output: LogOutput,
+    // mongos keeps no data; this only holds its log with LogOutput::DbPathFile
+    pub work_dir: PathBuf,
+    // The cluster's keyfile, required when the cluster uses auth
+    pub key_file: Option<PathBuf>,
+    pub tls: Option<TlsFiles>,
+}
+
+impl MongosConfig {
+    pub fn new(port: u16, config_db: &str, work_dir: &Path) -> Self {
+        Self {
+            port,
+            bind_ip: "127.0.0.1".to_string(),
+            config_db: config_db.to_string(),
+            log_output: LogOutput::default(),
+            work_dir: work_dir.to_path_buf(),
+            key_file: None,
+            tls: None,
         }
+    }
+}
+
+impl MongoProcess {
+    pub fn start(extracted_path: &Path, os: &Os, config: &MongodConfig, connection_string: String) -> Result<Self> {
+        let mut command = Command::new(executable(extracted_path, "mongod", os)?);
 
-        if !db_path.exists() {
-            std::fs::create_dir_all(db_path)?;
+        if !config.db_path.exists() {
+            std::fs::create_dir_all(&config.db_path)?;
         }
 
-        let mut command = Command::new(binary_path);
         command
             .arg("--port")
-            .arg(port.to_string())
+            .arg(config.port.to_string())
             .arg("--dbpath")
-            .arg(db_path)
+            .arg(&config.db_path)
             .arg("--bind_ip")
-            .arg(bind_ip);
+            .arg(&config.bind_ip);
         
-        if auth {
+        if config.auth {
             command.arg("--auth");
         }
 
-        let log_path = match os {
-            Os::Windows => "NUL",
-            _ => "/dev/null",
-        };
+        if let Some(name) = &config.replica_set {
+            command.arg("--replSet").arg(name);
+        }
+
+        if let Some(key_file) = &config.key_file {
+            command.arg("--keyFile").arg(key_file);
+        }
+
+        if let Some(tls) = &config.tls {
+            require_tls(&mut command, tls);
+        }
+
+        match config.cluster_role {
+            Some(ClusterRole::ConfigServer) => {
+                command.arg("--configsvr");
+            }
+            Some(ClusterRole::ShardServer) => {
+                command.arg("--shardsvr");
+            }
+            None => {}
+        }
+
+        Self::spawn(command, os, &config.log_output, &config.db_path, config.port, connection_string)
+    }
+
+    /// Starts a mongos router from the same install as mongod.
+    pub fn start_mongos(extracted_path: &Path, os: &Os, config: &MongosConfig, connection_string: String) -> Result<Self> {
+        let mut command = Command::new(executable(extracted_path, "mongos", os)?);
+
+        if !config.work_dir.exists() {
+            std::fs::create_dir_all(&config.work_dir)?;
+        }
 
         command
-            .arg("--quiet")
-            .arg("--logpath")
-            .arg(log_path);
+            .arg("--port")
+            .arg(config.port.to_string())
+            .arg("--configdb")
+            .arg(&config.config_db)
+            .arg("--bind_ip")
+            .arg(&config.bind_ip);
+
+        if let Some(key_file) = &config.key_file {
+            command.arg("--keyFile").arg(key_file);
+        }
+
+        if let Some(tls) = &config.tls {
+            require_tls(&mut command, tls);
+        }
+
+        Self::spawn(command, os, &config.log_output, &config.work_dir, config.port, connection_string)
+    }
 
+    // Sets up logging for `command` and runs it.
+    fn spawn(mut command: Command, os: &Os, log_output: &LogOutput, log_dir: &Path, port: u16, connection_string: String) -> Result<Self> {
+        // Without --logpath mongod logs to stdout, which we read ourselves
+        let log_path = log_output.log_path(log_dir);
+        match (log_output, &log_path) {
+            (LogOutput::Discard, _) => {
+                let null_path = match os {
+                    Os::Windows => "NUL",
+                    _ => "/dev/null",
+                };
+                command.arg("--quiet").arg("--logpath").arg(null_path);
+            }
+            (_, Some(path)) => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                command.arg("--logpath").arg(path).arg("--logappend");
+            }
+            (_, None) => {}
+        }
+
+        // stdout and stderr go to files rather than pipes, so a detached
+        // mongod can keep writing after we are gone
+        std::fs::create_dir_all(log_dir)?;
+        let program = Path::new(command.get_program()).file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        let stdout_path = log_dir.join(format!("{}.stdout", program));
+        let stderr_path = log_dir.join(format!("{}.stderr", program));
+        if log_path.is_none() && !matches!(log_output, LogOutput::Discard) {
+            command.stdout(std::fs::File::create(&stdout_path)?);
+        } else {
+            command.stdout(Stdio::null());
+        }
+        // Errors from before logging is set up only show up here
+        command.stderr(std::fs::File::create(&stderr_path)?);
         let child = command.spawn()?;
 
-        Ok(Self { child, connection_string })
+        let (log_events, _) = broadcast::channel(LOG_EVENT_CAPACITY);
+        let logs = match (log_output, log_path) {
+            (LogOutput::Buffer(lines), _) => follow(&stdout_path, *lines, None, Some(log_events.clone()))?,
+            (LogOutput::Callback(callback), _) => {
+                follow(&stdout_path, DEFAULT_LOG_LINES, Some(callback.clone()), Some(log_events.clone()))?
+            }
+            (_, Some(path)) => LogTail::File(path),
+            _ => LogTail::None,
+        };
+        let stderr = follow(&stderr_path, STDERR_LINES, None, None)?;
+
+        Ok(Self { child: Some(child), logs, stderr, log_events, connection_string, port })
+    }
+
+    /// The last `lines` lines mongod logged, oldest first. Empty with
+    /// [`LogOutput::Discard`].
+    pub fn recent_logs(&self, lines: usize) -> Vec<String> {
+        self.logs.recent(lines)
+    }
+
+    /// The last `lines` lines mongod wrote to stderr.
+    pub fn recent_stderr(&self, lines: usize) -> Vec<String> {
+        self.stderr.recent(lines)
+    }
+
+    /// Parsed log entries from now on. Entries are only published when the
+    /// log is read from mongod's output, i.e. with [`LogOutput::Buffer`] or
+    /// [`LogOutput::Callback`], and a receiver that falls more than 1024
+    /// entries behind sees `RecvError::Lagged`.
+    pub fn subscribe_logs(&self) -> broadcast::Receiver<LogEntry> {
+        self.log_events.subscribe()
+    }
+
+    // The exit status once mongod has exited on its own, `None` while it is
+    // running or after we stopped it.
+    pub(crate) fn exit_status(&mut self) -> Result<Option<ExitStatus>> {
+        match self.child.as_mut() {
+            Some(child) => Ok(child.try_wait()?),
+            None => Ok(None),
+        }
+    }
+
+    // Waits until mongod logs the entry with `id`, e.g. 23016 "Waiting for
+    // connections", and notices right away if it exits instead.
+    pub(crate) async fn wait_for_log_id(&mut self, id: u64, timeout: Duration) -> Result<Readiness> {
+        let mut events = self.subscribe_logs();
+        let deadline = tokio::time::Instant::now() + timeout;
+        // Entries logged before we subscribed, or missed while lagging, are
+        // only in the buffer; a log file is never published at all
+        let mut check_buffer = true;
+
+        loop {
+            if check_buffer && self.logged(id) {
+                return Ok(Readiness::Ready);
+            }
+            check_buffer = matches!(self.logs, LogTail::File(_));
+
+            if let Some(status) = self.exit_status()? {
+                return Ok(Readiness::Exited(status));
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Ok(Readiness::TimedOut);
+            }
+
+            match tokio::time::timeout(READINESS_POLL_INTERVAL, events.recv()).await {
+                Ok(Ok(entry)) if entry.id == id => return Ok(Readiness::Ready),
+                Ok(Err(broadcast::error::RecvError::Lagged(_))) => check_buffer = true,
+                _ => {}
+            }
+        }
+    }
+
+    // Gives the log reader a moment to catch up once mongod has exited, so
+    // error messages include its last words.
+    pub(crate) async fn flush_logs(&self) {
+        self.logs.wait_for_end(Duration::from_secs(1)).await;
+        self.stderr.wait_for_end(Duration::from_secs(1)).await;
+    }
+
+    // Turns a failed startup into the most specific error the exit status
+    // and output allow.
+    pub(crate) async fn startup_error(&self, readiness: Readiness, port: u16, db_path: &Path, timeout: Duration) -> StartError {
+        if let Readiness::Exited(_) = readiness {
+            self.flush_logs().await;
+        }
+        let mut output = self.recent_logs(DEFAULT_LOG_LINES);
+        output.extend(self.recent_stderr(STDERR_LINES));
+        let logs = output[output.len().saturating_sub(STARTUP_ERROR_LOG_LINES)..].to_vec();
+
+        let status = match readiness {
+            Readiness::Exited(status) => status,
+            _ => return StartError::Timeout { timeout, logs },
+        };
+        let mentions = |needles: &[&str]| output.iter().any(|line| needles.iter().any(|n| line.contains(n)));
+
+        if killed_by_sigill(&status) || mentions(&["Illegal instruction", "requires a CPU with AVX", "CPU does not support AVX"]) {
+            StartError::UnsupportedCpu { status, logs }
+        } else if status.code() == Some(EXIT_NET_ERROR) || mentions(&["Address already in use"]) {
+            StartError::AddressInUse { port, logs }
+        } else if mentions(&["DBPathInUse", "Unable to lock the lock file", "Unable to create/open the lock file"]) {
+            StartError::DbPathLocked { db_path: db_path.to_path_buf(), logs }
+        } else if status.code() == Some(EXIT_NEED_DOWNGRADE)
+            // mongod also logs featureCompatibilityVersion while starting
+            // normally, so only the fatal messages count
+            || mentions(&["UPGRADE PROBLEM", "Invalid featureCompatibilityVersion document"])
+        {
+            StartError::FcvMismatch { logs }
+        } else {
+            StartError::Exited { status, logs }
+        }
+    }
+
+    fn logged(&self, id: u64) -> bool {
+        self.logs
+            .recent(DEFAULT_LOG_LINES)
+            .iter()
+            .filter_map(|line| LogEntry::parse(line))
+            .any(|entry| entry.id == id)
+    }
+
+    /// OS process id, `None` once the process was killed or detached.
+    pub fn pid(&self) -> Option<u32> {
+        self.child.as_ref().map(Child::id)
     }
 
     pub fn kill(&mut self) -> Result<()> {
-        self.child.kill()?;
-        self.child.wait()?;
+        if let Some(mut child) = self.child.take() {
+            child.kill()?;
+            child.wait()?;
+        }
         Ok(())
     }
+
+    /// Stops mongod cleanly so WiredTiger can checkpoint and release its
+    /// lock: the admin `shutdown` command first, then SIGTERM, then kill.
+    /// Each graceful step gets `timeout` to finish.
+    pub async fn shutdown(&mut self, timeout: Duration) -> Result<ShutdownMethod> {
+        let Some(child) = self.child.as_mut() else {
+            return Ok(ShutdownMethod::AlreadyExited);
+        };
+
+        let method = if child.try_wait()?.is_some() {
+            ShutdownMethod::AlreadyExited
+        } else if send_shutdown_command(&self.connection_string, timeout).await && wait_for_exit(child, timeout).await? {
+            ShutdownMethod::Command
+        } else if send_sigterm(child) && wait_for_exit(child, timeout).await? {
+            ShutdownMethod::Signal
+        } else {
+            child.kill()?;
+            child.wait()?;
+            ShutdownMethod::Killed
+        };
+
+        self.child = None;
+        Ok(method)
+    }
+
+    /// Lets mongod outlive this handle and returns its process id. The
+    /// caller is responsible for stopping it.
+    ///
+    /// mongod's stdout and stderr go to `mongod.stdout` and `mongod.stderr`
+    /// in the db path, which the detached process keeps writing to; lines
+    /// written after this call no longer reach [`recent_logs`](Self::recent_logs)
+    /// or a [`LogOutput::Callback`].
+    // Not waiting is the point; the process outlives us
+    #[allow(clippy::zombie_processes)]
+    pub fn detach(&mut self) -> Result<u32> {
+        let child = self.child.take().ok_or_else(|| anyhow!("mongod has already been stopped"))?;
+        self.logs.stop();
+        self.stderr.stop();
+        Ok(child.id())
+    }
+
+    // Sends SIGTERM without waiting, so several processes can shut down in
+    // parallel before each is dropped.
+    pub(crate) fn begin_shutdown(&mut self) {
+        if let Some(child) = self.child.as_mut() {
+            if let Ok(None) = child.try_wait() {
+                send_sigterm(child);
+            }
+        }
+    }
+}
+
+impl Drop for MongoProcess {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            let _ = terminate(&mut child, DROP_GRACE_PERIOD);
+        }
+    }
+}
+
+// Sends SIGTERM, which mongod handles as a clean shutdown, and kills the
+// process if it is still running after `grace`.
+pub(crate) fn terminate(child: &mut Child, grace: Duration) -> Result<()> {
+    if child.try_wait()?.is_some() {
+        return Ok(());
+    }
+
+    if send_sigterm(child) {
+        let deadline = std::time::Instant::now() + grace;
+        while std::time::Instant::now() < deadline {
+            if child.try_wait()?.is_some() {
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    child.kill()?;
+    child.wait()?;
+    Ok(())
+}
+
+// Whether the signal was delivered. Windows has no equivalent, so callers
+// fall through to killing the process there.
+fn send_sigterm(child: &Child) -> bool {
+    #[cfg(unix)]
+    {
+        // SAFETY: `kill` has no memory-safety requirements; the child has not
+        // been reaped yet, so the pid still refers to our mongod.
+        unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) == 0 }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = child;
+        false
+    }
+}
+
+// Whether mongod accepted the `shutdown` command. It drops the connection
+// while shutting down, so a network error after connecting counts as success.
+async fn send_shutdown_command(connection_string: &str, timeout: Duration) -> bool {
+    use mongodb::bson::doc;
+    use mongodb::error::ErrorKind;
+
+    let Ok(mut options) = mongodb::options::ClientOptions::parse(connection_string).await else {
+        return false;
+    };
+    options.direct_connection = Some(true);
+    options.connect_timeout = Some(timeout);
+    options.server_selection_timeout = Some(timeout);
+    let Ok(client) = mongodb::Client::with_options(options) else {
+        return false;
+    };
+
+    let admin = client.database("admin");
+    let command = admin.run_command(doc! { "shutdown": 1 }, None);
+    match tokio::time::timeout(timeout, command).await {
+        Ok(Ok(_)) => true,
+        Ok(Err(e)) => !matches!(
+            *e.kind,
+            ErrorKind::Command(_) | ErrorKind::Authentication { .. } | ErrorKind::ServerSelection { .. }
+        ),
+        Err(_) => false,
+    }
+}
+
+async fn wait_for_exit(child: &mut Child, timeout: Duration) -> Result<bool> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        if child.try_wait()?.is_some() {
+            return Ok(true);
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Ok(false);
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
+
+pub fn executable_name(name: &str, os: &Os) -> String {
+    match os {
+        Os::Windows => format!("{}.exe", name),
+        _ => name.to_string(),
+    }
+}
+
+/// Looks for `name` in `dirs`, or in the directories on `PATH` if `None`.
+pub fn find_on_path(name: &str, dirs: Option<&[PathBuf]>) -> Option<PathBuf> {
+    let dirs = match dirs {
+        Some(dirs) => dirs.to_vec(),
+        None => std::env::split_paths(&std::env::var_os("PATH")?).collect(),
+    };
+    dirs.into_iter().map(|dir| dir.join(name)).find(|candidate| candidate.is_file())
+}
+
+// Clients need the CA to trust the server but no certificate of their own.
+fn require_tls(command: &mut Command, tls: &TlsFiles) {
+    command
+        .arg("--tlsMode")
+        .arg("requireTLS")
+        .arg("--tlsCertificateKeyFile")
+        .arg(&tls.certificate_key_file)
+        .arg("--tlsCAFile")
+        .arg(&tls.ca_file)
+        .arg("--tlsAllowConnectionsWithoutCertificates");
+}
+
+// Locates `name` in an install and checks that it may be run. The binary is
+// never modified: it may belong to another user or sit on a read-only mount.
+fn executable(extracted_path: &Path, name: &str, os: &Os) -> Result<PathBuf> {
+    let binary_name = executable_name(name, os);
+
+    let binary_path = find_binary(extracted_path, &binary_name)
+        .ok_or_else(|| anyhow!("Could not find {} in extracted directory", binary_name))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if std::fs::metadata(&binary_path)?.permissions().mode() & 0o111 == 0 {
+            return Err(anyhow!("{} is not executable", binary_path.display()));
+        }
+    }
+    Ok(binary_path)
+}
+
+// Sets the execute bits on a binary we extracted ourselves, for archives
+// that do not record file modes.
+pub(crate) fn make_executable(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mut perms = std::fs::metadata(path)?.permissions();
+        if perms.mode() & 0o111 == 0 {
+            perms.set_mode(perms.mode() | 0o755);
+            std::fs::set_permissions(path, perms)?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+    Ok(())
+}
+
+pub(crate) fn find_binary(root: &Path, name: &str) -> Option<PathBuf> {
+    // A mongod found on PATH stands in for its directory
+    if root.is_file() && root.file_name().and_then(|n| n.to_str()) != Some(name) {
+        return root.parent().map(|dir| dir.join(name)).filter(|path| path.is_file());
+    }
+    // Selective extraction puts binaries at a known location
+    let direct = root.join("bin").join(name);
+    if direct.is_file() {
+        return Some(direct);
+    }
+    find_binary_recursive(root, name)
 }
 
-fn find_binary(root: &Path, name: &str) -> Option<PathBuf> {
+fn find_binary_recursive(root: &Path, name: &str) -> Option<PathBuf> {
     if root.is_file() {
         if root.file_name()?.to_str()? == name {
             return Some(root.to_path_buf());
@@ -86,7 +670,7 @@ fn find_binary(root: &Path, name: &str) -> Option<PathBuf> {
         for entry in std::fs::read_dir(root).ok()? {
             let entry = entry.ok()?;
             let path = entry.path();
-            if let Some(found) = find_binary(&path, name) {
+            if let Some(found) = find_binary_recursive(&path, name) {
                 return Some(found);
             }
         }
@@ -0,0 +1,126 @@
+use anyhow::{anyhow, Result};
+use mongodb::bson::{doc, Document};
+use mongodb::options::ClientOptions;
+use mongodb::Client;
+use rand::distributions::{Alphanumeric, DistString};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::tls::{with_tls, TlsFiles};
+
+// replSetInitiate on a member that already has a config, e.g. after a restart
+// with the same db path.
+const ALREADY_INITIALIZED: i32 = 23;
+
+// replSetInitiate without credentials once the set has users. That only
+// happens after it was initiated, which `hello` confirms.
+const UNAUTHORIZED: i32 = 13;
+
+// mongod accepts 6 to 1024 base64 characters.
+const KEYFILE_LENGTH: usize = 756;
+
+const PRIMARY_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Options for talking to exactly one member, whatever its replica set
+/// state.
+pub async fn direct_options(host: &str, tls: Option<&TlsFiles>, timeout: Duration) -> Result<ClientOptions> {
+    let mut options = ClientOptions::parse(with_tls(format!("mongodb://{}/", host), tls)).await?;
+    options.direct_connection = Some(true);
+    options.connect_timeout = Some(timeout);
+    options.server_selection_timeout = Some(timeout);
+    Ok(options)
+}
+
+/// A client talking to exactly one member, whatever its replica set state.
+pub async fn direct_client(host: &str, tls: Option<&TlsFiles>, timeout: Duration) -> Result<Client> {
+    Ok(Client::with_options(direct_options(host, tls, timeout).await?)?)
+}
+
+/// Writes a random keyfile for internal authentication to `dir/keyfile`,
+/// readable by the owner only as mongod requires. An existing keyfile is
+/// kept, so members restarted on the same data can still talk to the rest.
+pub fn write_keyfile(dir: &Path) -> Result<PathBuf> {
+    let path = dir.join("keyfile");
+    if path.is_file() {
+        return Ok(path);
+    }
+    std::fs::create_dir_all(dir)?;
+    let key = Alphanumeric.sample_string(&mut rand::thread_rng(), KEYFILE_LENGTH);
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    // Created with its final mode, so the key is never readable by others
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o400);
+    }
+    options.open(&path)?.write_all(key.as_bytes())?;
+    Ok(path)
+}
+
+/// The `replSetInitiate` config for a set with one voting member.
+pub fn single_node_config(name: &str, host: &str) -> Document {
+    doc! {
+        "_id": name,
+        "members": [{ "_id": 0, "host": host }],
+    }
+}
+
+/// Runs `replSetInitiate` through `client`, which must be connected directly
+/// to one of the members. A set that is already initiated is left alone.
+/// With auth the client needs no credentials: the localhost exception
+/// allows initiating until the first user exists.
+pub async fn initiate(client: &Client, config: Document) -> Result<()> {
+    let result = client
+        .database("admin")
+        .run_command(doc! { "replSetInitiate": config }, None)
+        .await;
+    match result {
+        Ok(_) => Ok(()),
+        Err(e) => match *e.kind {
+            mongodb::error::ErrorKind::Command(ref command) if command.code == ALREADY_INITIALIZED => Ok(()),
+            mongodb::error::ErrorKind::Command(ref command)
+                if command.code == UNAUTHORIZED && is_initiated(client).await =>
+            {
+                Ok(())
+            }
+            _ => Err(anyhow!("replSetInitiate failed: {}", e)),
+        },
+    }
+}
+
+// `hello` needs no credentials and only reports a set name once the member
+// has a replica set config.
+async fn is_initiated(client: &Client) -> bool {
+    match client.database("admin").run_command(doc! { "hello": 1 }, None).await {
+        Ok(reply) => reply.get_str("setName").is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Waits until the member behind `client` reports itself as primary.
+pub async fn wait_for_primary(client: &Client, timeout: Duration) -> Result<()> {
+    let deadline = tokio::time::Instant::now() + timeout;
+    loop {
+        if let Ok(reply) = client.database("admin").run_command(doc! { "hello": 1 }, None).await {
+            if reply.get_bool("isWritablePrimary").unwrap_or(false) {
+                return Ok(());
+            }
+        }
+        if tokio::time::Instant::now() >= deadline {
+            return Err(anyhow!("Timed out waiting for a replica set primary"));
+        }
+        tokio::time::sleep(PRIMARY_POLL_INTERVAL).await;
+    }
+}
+
+/// A connection string for the whole set, so the driver follows the primary.
+pub fn connection_string(hosts: &[String], name: &str, credentials: Option<(&str, &str)>) -> String {
+    let credentials = match credentials {
+        Some((username, password)) => format!("{}:{}@", username, password),
+        None => String::new(),
+    };
+    format!("mongodb://{}{}/?replicaSet={}", credentials, hosts.join(","), name)
+}
@@ -0,0 +1,206 @@
+use anyhow::{anyhow, Result};
+use mongodb::bson::{doc, Document};
+use mongodb::Client;
+use std::time::Duration;
+
+use crate::cluster::{check_settings, credentials, shutdown_all, MongoCluster, ReplicaSetConfig};
+use crate::downloader::get_os;
+use crate::process::{ClusterRole, MongoProcess};
+use crate::tls::with_tls;
+use crate::{replset, setup_user, Launch, MongoEmbedded};
+
+const CONFIG_REPLICA_SET: &str = "configRS";
+
+/// The shape of a sharded cluster started by [`MongoShardedCluster`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardedClusterConfig {
+    pub shards: usize,
+    // Members of each shard's replica set
+    pub shard_members: usize,
+    // Members of the config server replica set
+    pub config_servers: usize,
+    // mongos processes
+    pub routers: usize,
+}
+
+impl ShardedClusterConfig {
+    /// `shards` single-member shards, one config server and one mongos.
+    pub fn new(shards: usize) -> Self {
+        Self { shards, shard_members: 1, config_servers: 1, routers: 1 }
+    }
+
+    pub fn set_shard_members(mut self, members: usize) -> Self {
+        self.shard_members = members;
+        self
+    }
+
+    pub fn set_config_servers(mut self, members: usize) -> Self {
+        self.config_servers = members;
+        self
+    }
+
+    pub fn set_routers(mut self, routers: usize) -> Self {
+        self.routers = routers;
+        self
+    }
+}
+
+/// A local sharded cluster: a config server replica set, shard replica sets
+/// and mongos routers, all from one install. Dropping it stops the routers,
+/// then the shards, then the config servers; the processes of each tier
+/// shut down in parallel.
+pub struct MongoShardedCluster {
+    // Lists every mongos; clients should only ever talk to these
+    pub connection_string: String,
+    pub routers: Vec<MongoProcess>,
+    pub shards: Vec<MongoCluster>,
+    pub config_servers: MongoCluster,
+}
+
+impl MongoShardedCluster {
+    /// Starts the cluster with the settings of `embedded`, under
+    /// `embedded.db_path`, and adds every shard. With selective extraction
+    /// mongos is extracted as well.
+    pub async fn start(embedded: &MongoEmbedded, config: ShardedClusterConfig) -> Result<Self> {
+        check_settings(embedded)?;
+        if config.shards == 0 || config.routers == 0 {
+            return Err(anyhow!("A sharded cluster needs at least one shard and one mongos"));
+        }
+        if config.shard_members == 0 || config.config_servers == 0 {
+            return Err(anyhow!("Shards and config servers need at least one member each"));
+        }
+
+        let mut settings = embedded.clone();
+        if let Some(binaries) = settings.extract_binaries.as_mut() {
+            if !binaries.iter().any(|b| b == "mongos") {
+                binaries.push("mongos".to_string());
+            }
+        }
+        // One keyfile and one set of certificates for every member and router
+        settings.resolve_tls()?;
+        if credentials(embedded).is_some() && settings.key_file.is_none() {
+            settings.key_file = Some(replset::write_keyfile(&settings.db_path)?);
+        }
+        let install_root = settings.prepare(&mut |_| {}).await?;
+        let os = get_os()?;
+        let under = |dir: &str| {
+            let mut part = settings.clone();
+            part.db_path = settings.db_path.join(dir);
+            part
+        };
+
+        let config_servers = MongoCluster::start_from(
+            &under("config"),
+            ReplicaSetConfig::new(CONFIG_REPLICA_SET, config.config_servers),
+            Some(ClusterRole::ConfigServer),
+            install_root.clone(),
+            os.clone(),
+            &mut |_| {},
+        )
+        .await?;
+
+        let mut shards = Vec::new();
+        for i in 0..config.shards {
+            let name = format!("shard{}", i);
+            let shard = MongoCluster::start_from(
+                &under(&name),
+                ReplicaSetConfig::new(&name, config.shard_members),
+                Some(ClusterRole::ShardServer),
+                install_root.clone(),
+                os.clone(),
+                &mut |_| {},
+            )
+            .await?;
+            shards.push(shard);
+        }
+
+        let mut routers = Vec::new();
+        let mut hosts = Vec::new();
+        let config_db = config_servers.seed_list();
+        for i in 0..config.routers {
+            let mut router = under(&format!("mongos{}", i));
+            router.port = 0;
+            router.replica_set = None;
+            let (process, _) = router.start_process(&install_root, &os, &Launch::Mongos(config_db.clone())).await?;
+            hosts.push(settings.member_host(process.port));
+            routers.push(process);
+        }
+
+        let user = match credentials(embedded) {
+            Some((username, password)) => {
+                // The config servers already hold the user; this checks it
+                // works through mongos
+                let options = replset::direct_options(&hosts[0], settings.tls_files()?, embedded.startup_timeout).await?;
+                setup_user(&options, username, password, &mut |_| {}).await?;
+                format!("{}:{}@", username, password)
+            }
+            None => String::new(),
+        };
+
+        let cluster = MongoShardedCluster {
+            connection_string: with_tls(format!("mongodb://{}{}/", user, hosts.join(",")), settings.tls_files()?),
+            routers,
+            shards,
+            config_servers,
+        };
+        for shard in &cluster.shards {
+            cluster.add_shard(shard).await?;
+        }
+        Ok(cluster)
+    }
+
+    async fn admin_command(&self, command: Document) -> Result<Document> {
+        let client = Client::with_uri_str(&self.connection_string).await?;
+        Ok(client.database("admin").run_command(command, None).await?)
+    }
+
+    /// Adds a replica set to the cluster as a shard. It must have been
+    /// started as a shard server.
+    pub async fn add_shard(&self, shard: &MongoCluster) -> Result<()> {
+        self.admin_command(doc! { "addShard": shard.seed_list(), "name": &shard.name })
+            .await
+            .map_err(|e| anyhow!("addShard failed: {}", e))?;
+        Ok(())
+    }
+
+    /// Enables sharding for `database`. Only required before MongoDB 6.0,
+    /// harmless after.
+    pub async fn enable_sharding(&self, database: &str) -> Result<()> {
+        self.admin_command(doc! { "enableSharding": database })
+            .await
+            .map_err(|e| anyhow!("enableSharding failed: {}", e))?;
+        Ok(())
+    }
+
+    /// Shards `namespace` (`db.collection`) on `key`, e.g.
+    /// `doc! { "userId": "hashed" }`.
+    pub async fn shard_collection(&self, namespace: &str, key: Document) -> Result<()> {
+        self.admin_command(doc! { "shardCollection": namespace, "key": key })
+            .await
+            .map_err(|e| anyhow!("shardCollection failed: {}", e))?;
+        Ok(())
+    }
+
+    /// Stops the routers, then the shards, then the config servers, each
+    /// tier in parallel, without blocking the thread like a drop does.
+    pub async fn shutdown(&mut self, timeout: Duration) -> Result<()> {
+        shutdown_all(std::mem::take(&mut self.routers), timeout).await?;
+        let shards = self.shards.iter_mut().flat_map(|shard| shard.members.iter_mut());
+        shutdown_all(shards.filter_map(|m| m.process.take()).collect(), timeout).await?;
+        self.config_servers.shutdown(timeout).await
+    }
+}
+
+impl Drop for MongoShardedCluster {
+    fn drop(&mut self) {
+        for router in &mut self.routers {
+            router.begin_shutdown();
+        }
+        self.routers.clear();
+        for shard in &mut self.shards {
+            shard.begin_shutdown();
+        }
+        self.shards.clear();
+        // The config servers stop when the field is dropped
+    }
+}
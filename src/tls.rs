@@ -0,0 +1,78 @@
+use anyhow::Result;
+use rcgen::{BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, KeyPair, KeyUsagePurpose};
+use std::path::{Path, PathBuf};
+
+/// PEM files mongod serves TLS with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsFiles {
+    // The server certificate followed by its private key
+    pub certificate_key_file: PathBuf,
+    // The CA that signed it; clients need it to trust the server
+    pub ca_file: PathBuf,
+}
+
+/// How mongod gets its certificate when TLS is required.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TlsOptions {
+    // A throwaway CA and server certificate are generated in the db path
+    // at every start
+    Generated,
+    // Your own PEM files
+    Files(TlsFiles),
+}
+
+/// Generates a CA and a server certificate for `hosts` (names or IP
+/// addresses) into `dir/tls`, replacing any from an earlier start. The
+/// certificate is also valid for client authentication, which members of a
+/// replica set need when they connect to each other.
+pub fn generate(dir: &Path, hosts: &[String]) -> Result<TlsFiles> {
+    let dir = dir.join("tls");
+    std::fs::create_dir_all(&dir)?;
+
+    let ca_key = KeyPair::generate()?;
+    let mut ca_params = CertificateParams::new(Vec::<String>::new())?;
+    ca_params.distinguished_name.push(DnType::CommonName, "mongo-embedded test CA");
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    ca_params.key_usages = vec![KeyUsagePurpose::KeyCertSign, KeyUsagePurpose::CrlSign];
+    let ca = ca_params.self_signed(&ca_key)?;
+
+    let server_key = KeyPair::generate()?;
+    let mut server_params = CertificateParams::new(hosts.to_vec())?;
+    server_params.distinguished_name.push(DnType::CommonName, "mongod");
+    server_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth, ExtendedKeyUsagePurpose::ClientAuth];
+    let server = server_params.signed_by(&server_key, &ca, &ca_key)?;
+
+    let files = TlsFiles {
+        certificate_key_file: dir.join("server.pem"),
+        ca_file: dir.join("ca.pem"),
+    };
+    std::fs::write(&files.ca_file, ca.pem())?;
+    std::fs::write(&files.certificate_key_file, server.pem() + &server_key.serialize_pem())?;
+    Ok(files)
+}
+
+/// Adds `tls=true` and the CA file to a connection string, so the driver
+/// trusts the server without further setup.
+pub fn with_tls(uri: String, files: Option<&TlsFiles>) -> String {
+    let Some(files) = files else { return uri };
+    let separator = if uri.contains('?') {
+        "&"
+    } else if uri.ends_with('/') {
+        "?"
+    } else {
+        "/?"
+    };
+    format!("{}{}tls=true&tlsCAFile={}", uri, separator, encode_path(&files.ca_file))
+}
+
+// Percent-encodes what would end or confuse a URI option value.
+fn encode_path(path: &Path) -> String {
+    path.display()
+        .to_string()
+        .chars()
+        .map(|c| match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '-' | '.' | '_' | '~' | '/' => c.to_string(),
+            c => c.to_string().bytes().map(|b| format!("%{:02X}", b)).collect(),
+        })
+        .collect()
+}
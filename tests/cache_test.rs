@@ -0,0 +1,120 @@
+use mongo_embedded::cache::{install_archive, is_complete_install, InstallLock, InstallMarker};
+use std::time::Duration;
+
+#[tokio::test]
+async fn test_second_installer_waits_for_lock() {
+    let dir = std::env::temp_dir().join("mongo_test_install_lock");
+    let lock_path = dir.join("7.0.2.lock");
+
+    let first = InstallLock::acquire(&lock_path).await.unwrap();
+    assert!(lock_path.is_file());
+
+    let waiting = tokio::time::timeout(Duration::from_millis(500), InstallLock::acquire(&lock_path)).await;
+    assert!(waiting.is_err(), "The lock should still be held");
+
+    let path = lock_path.clone();
+    let second = tokio::spawn(async move { InstallLock::acquire(&path).await });
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    drop(first);
+
+    tokio::time::timeout(Duration::from_secs(5), second)
+        .await
+        .expect("The lock should be handed over once released")
+        .unwrap()
+        .unwrap();
+}
+
+fn build_tgz(path: &std::path::Path, entries: &[(&str, &[u8])]) {
+    let file = std::fs::File::create(path).unwrap();
+    let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::fast());
+    let mut builder = tar::Builder::new(encoder);
+    for (name, data) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder.append_data(&mut header, name, *data).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap();
+}
+
+fn marker() -> InstallMarker {
+    InstallMarker {
+        version: "7.0.2".to_string(),
+        archive: "mongodb.tgz".to_string(),
+        sha256: "abc".to_string(),
+        binaries: None,
+    }
+}
+
+fn install_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("mongo_test_install_{}", name));
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_install_writes_marker() {
+    let dir = install_dir("marker");
+    let archive = dir.join("mongodb.tgz");
+    build_tgz(&archive, &[("mongodb-7.0.2/bin/mongod", b"#!/bin/sh\n")]);
+
+    let target = dir.join("extracted/7.0.2");
+    install_archive(&archive, &target, &marker()).unwrap();
+
+    assert!(is_complete_install(&target, &["mongod".to_string()]));
+    assert_eq!(InstallMarker::read(&target).unwrap(), marker());
+    assert!(!is_complete_install(&target, &["mongos".to_string()]));
+}
+
+#[test]
+fn test_incomplete_install_is_replaced() {
+    let dir = install_dir("repair");
+    let archive = dir.join("mongodb.tgz");
+    build_tgz(&archive, &[("mongodb-7.0.2/bin/mongod", b"#!/bin/sh\n")]);
+
+    // A crash mid-extraction leaves a tree without the marker
+    let target = dir.join("extracted/7.0.2");
+    std::fs::create_dir_all(target.join("mongodb-7.0.2/bin")).unwrap();
+    std::fs::write(target.join("mongodb-7.0.2/bin/mongod"), b"#!/bin").unwrap();
+    assert!(!is_complete_install(&target, &["mongod".to_string()]));
+
+    install_archive(&archive, &target, &marker()).unwrap();
+    assert!(is_complete_install(&target, &["mongod".to_string()]));
+    assert_eq!(std::fs::read(target.join("mongodb-7.0.2/bin/mongod")).unwrap(), b"#!/bin/sh\n");
+}
+
+#[test]
+fn test_failed_extraction_leaves_nothing_behind() {
+    let dir = install_dir("corrupt");
+    let archive = dir.join("mongodb.tgz");
+    std::fs::write(&archive, b"not a gzip archive").unwrap();
+
+    let extracted = dir.join("extracted");
+    assert!(install_archive(&archive, &extracted.join("7.0.2"), &marker()).is_err());
+    let leftovers = std::fs::read_dir(&extracted).map(|d| d.count()).unwrap_or(0);
+    assert_eq!(leftovers, 0);
+}
+
+#[test]
+fn test_selective_install() {
+    let dir = install_dir("selective");
+    let archive = dir.join("mongodb.tgz");
+    build_tgz(&archive, &[
+        ("mongodb-7.0.2/LICENSE-Community.txt", b"license"),
+        ("mongodb-7.0.2/bin/mongod", b"mongod"),
+        ("mongodb-7.0.2/bin/mongos", b"mongos"),
+    ]);
+
+    let target = dir.join("extracted/7.0.2");
+    let marker = InstallMarker { binaries: Some(vec!["mongod".to_string()]), ..marker() };
+    install_archive(&archive, &target, &marker).unwrap();
+
+    assert!(is_complete_install(&target, &["mongod".to_string()]));
+    assert!(!is_complete_install(&target, &["mongod".to_string(), "mongos".to_string()]));
+    assert!(!target.join("mongodb-7.0.2").exists());
+    assert_eq!(InstallMarker::read(&target).unwrap(), marker);
+}
@@ -0,0 +1,62 @@
+use mongo_embedded::downloader::{Arch, Os};
+use mongo_embedded::platform::LinuxDistro;
+use mongo_embedded::ReleaseCatalog;
+use std::path::Path;
+
+fn fixture_catalog() -> ReleaseCatalog {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/full.json");
+    ReleaseCatalog::from_file(&path).expect("Failed to load fixture catalog")
+}
+
+#[test]
+fn test_catalog_lists_versions() {
+    let catalog = fixture_catalog();
+    let versions: Vec<&str> = catalog.versions().collect();
+    assert_eq!(versions, vec!["8.0.4", "7.0.2"]);
+}
+
+#[test]
+fn test_resolve_linux_with_distro_fallback() {
+    let catalog = fixture_catalog();
+    let noble = LinuxDistro::parse("ID=ubuntu\nVERSION_ID=\"24.04\"\n");
+
+    let url = catalog.resolve("8.0.4", &Os::Linux, &Arch::X86_64, Some(&noble)).unwrap();
+    assert_eq!(url.filename, "mongodb-linux-x86_64-ubuntu2404-8.0.4.tgz");
+    assert_eq!(url.sha256.as_deref().map(str::len), Some(64));
+
+    // No ubuntu2404 build in the 7.0.2 feed, so the 22.04 one is picked
+    let url = catalog.resolve("7.0.2", &Os::Linux, &Arch::X86_64, Some(&noble)).unwrap();
+    assert_eq!(url.url, "https://fastdl.mongodb.org/linux/mongodb-linux-x86_64-ubuntu2204-7.0.2.tgz");
+    assert_eq!(url.path, "linux/mongodb-linux-x86_64-ubuntu2204-7.0.2.tgz");
+}
+
+#[test]
+fn test_resolve_skips_enterprise_builds() {
+    let catalog = fixture_catalog();
+    let url = catalog.resolve("7.0.2", &Os::Linux, &Arch::X86_64, None).unwrap();
+    assert!(url.url.starts_with("https://fastdl.mongodb.org/"));
+}
+
+#[test]
+fn test_resolve_macos_and_windows() {
+    let catalog = fixture_catalog();
+    let url = catalog.resolve("7.0.2", &Os::MacOs, &Arch::Aarch64, None).unwrap();
+    assert_eq!(url.filename, "mongodb-macos-arm64-7.0.2.tgz");
+
+    let url = catalog.resolve("7.0.2", &Os::Windows, &Arch::X86_64, None).unwrap();
+    assert_eq!(url.filename, "mongodb-windows-x86_64-7.0.2.zip");
+}
+
+#[test]
+fn test_unpublished_combinations_fail() {
+    let catalog = fixture_catalog();
+    let err = catalog.resolve("6.0.0", &Os::Linux, &Arch::X86_64, None).unwrap_err();
+    assert!(err.to_string().contains("not listed"));
+
+    let debian12 = LinuxDistro::parse("ID=debian\nVERSION_ID=\"12\"\n");
+    let url = catalog.resolve("7.0.2", &Os::Linux, &Arch::X86_64, Some(&debian12)).unwrap();
+    assert_eq!(url.filename, "mongodb-linux-x86_64-ubuntu2204-7.0.2.tgz");
+
+    let err = catalog.resolve("7.0.2", &Os::Windows, &Arch::Aarch64, None).unwrap_err();
+    assert!(err.to_string().contains("no published archive"));
+}
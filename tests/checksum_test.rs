@@ -0,0 +1,43 @@
+use mongo_embedded::downloader::{parse_sha256_sidecar, sha256_file, verify_checksum};
+use mongo_embedded::ChecksumMismatch;
+
+// sha256("hello")
+const HELLO_SHA256: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+fn write_archive(name: &str, contents: &[u8]) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join("mongo_test_checksum");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn test_verify_matching_checksum() {
+    let path = write_archive("good.tgz", b"hello");
+    assert_eq!(sha256_file(&path).unwrap(), HELLO_SHA256);
+
+    let actual = verify_checksum(&path, &HELLO_SHA256.to_uppercase()).unwrap();
+    assert_eq!(actual, HELLO_SHA256);
+    assert!(path.exists());
+}
+
+#[test]
+fn test_mismatch_removes_archive() {
+    let path = write_archive("truncated.tgz", b"hell");
+
+    let err = verify_checksum(&path, HELLO_SHA256).unwrap_err();
+    let mismatch = err.downcast_ref::<ChecksumMismatch>().expect("Expected a ChecksumMismatch error");
+    assert_eq!(mismatch.expected, HELLO_SHA256);
+    assert_ne!(mismatch.actual, HELLO_SHA256);
+    assert!(!path.exists(), "Corrupt archive should be removed");
+}
+
+#[test]
+fn test_parse_sidecar() {
+    let sidecar = format!("{}  mongodb-linux-x86_64-ubuntu2204-7.0.2.tgz\n", HELLO_SHA256);
+    assert_eq!(parse_sha256_sidecar(&sidecar).unwrap(), HELLO_SHA256);
+
+    assert!(parse_sha256_sidecar("").is_err());
+    assert!(parse_sha256_sidecar("<html>Not Found</html>").is_err());
+}
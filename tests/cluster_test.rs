@@ -0,0 +1,142 @@
+mod common;
+
+#[cfg(unix)]
+use common::{recorded_args, recording_embedded, work_dir};
+use mongo_embedded::{MemberConfig, MongoCluster, MongoEmbedded, ReplicaSetConfig};
+
+fn hosts(count: usize) -> Vec<String> {
+    (0..count).map(|i| format!("127.0.0.1:{}", 27017 + i)).collect()
+}
+
+#[test]
+fn test_config_document() {
+    let config = ReplicaSetConfig::new("rs0", 3)
+        .set_member(1, MemberConfig::new().set_priority(2.0))
+        .unwrap()
+        .set_member(2, MemberConfig::new().set_hidden(true).set_votes(0))
+        .unwrap();
+    let document = config.to_document(&hosts(3));
+
+    assert_eq!(document.get_str("_id").unwrap(), "rs0");
+    let members = document.get_array("members").unwrap();
+    assert_eq!(members.len(), 3);
+    let second = members[1].as_document().unwrap();
+    assert_eq!(second.get_i32("_id").unwrap(), 1);
+    assert_eq!(second.get_str("host").unwrap(), "127.0.0.1:27018");
+    assert_eq!(second.get_f64("priority").unwrap(), 2.0);
+    let hidden = members[2].as_document().unwrap();
+    assert!(hidden.get_bool("hidden").unwrap());
+    assert_eq!(hidden.get_f64("priority").unwrap(), 0.0, "Hidden members cannot be elected");
+    assert_eq!(hidden.get_i32("votes").unwrap(), 0);
+}
+
+#[test]
+fn test_config_document_with_arbiter() {
+    let config = ReplicaSetConfig::new("rs0", 2).set_arbiter(true);
+    let members = config.to_document(&hosts(3)).get_array("members").unwrap().clone();
+
+    assert_eq!(members.len(), 3);
+    let arbiter = members[2].as_document().unwrap();
+    assert_eq!(arbiter.get_i32("_id").unwrap(), 2);
+    assert_eq!(arbiter.get_str("host").unwrap(), "127.0.0.1:27019");
+    assert!(arbiter.get_bool("arbiterOnly").unwrap());
+}
+
+#[test]
+fn test_set_member_out_of_range() {
+    let err = ReplicaSetConfig::new("rs0", 2).set_member(2, MemberConfig::new()).expect_err("There is no third member");
+    assert!(err.to_string().contains("no member 2"), "Unexpected error: {}", err);
+}
+
+#[tokio::test]
+async fn test_hidden_member_needs_priority_zero() {
+    let member = MemberConfig::new().set_hidden(true).set_priority(1.0);
+    let config = ReplicaSetConfig::new("rs0", 2).set_member(1, member).unwrap();
+    assert!(config.validate().is_err());
+
+    let mongo = MongoEmbedded::new("7.0.2").unwrap();
+    let err = MongoCluster::start(&mongo, config).await.err().expect("A hidden member cannot be electable");
+    assert!(err.to_string().contains("hidden"), "Unexpected error: {}", err);
+}
+
+#[test]
+fn test_non_voting_member_needs_priority_zero() {
+    let member = MemberConfig::new().set_votes(0);
+    let config = ReplicaSetConfig::new("rs0", 3).set_member(2, member.clone()).unwrap();
+    let err = config.validate().expect_err("A member without a vote cannot be electable");
+    assert!(err.to_string().contains("priority"), "Unexpected error: {}", err);
+
+    let config = ReplicaSetConfig::new("rs0", 3).set_member(2, member.set_priority(0.0)).unwrap();
+    config.validate().unwrap();
+}
+
+#[test]
+fn test_at_most_seven_voting_members() {
+    ReplicaSetConfig::new("rs0", 7).validate().unwrap();
+    ReplicaSetConfig::new("rs0", 6).set_arbiter(true).validate().expect("Six members and an arbiter are seven votes");
+
+    let err = ReplicaSetConfig::new("rs0", 7).set_arbiter(true).validate().expect_err("The arbiter is an eighth vote");
+    assert!(err.to_string().contains("at most 7"), "Unexpected error: {}", err);
+
+    let non_voting = MemberConfig::new().set_votes(0).set_priority(0.0);
+    let config = ReplicaSetConfig::new("rs0", 8).set_member(7, non_voting).unwrap();
+    config.validate().unwrap();
+}
+
+#[tokio::test]
+async fn test_cluster_needs_members() {
+    let mongo = MongoEmbedded::new("7.0.2").unwrap();
+    let err = MongoCluster::start(&mongo, ReplicaSetConfig::new("rs0", 0)).await.err().expect("An empty set cannot start");
+    assert!(err.to_string().contains("at least one"), "Unexpected error: {}", err);
+}
+
+#[tokio::test]
+async fn test_cluster_needs_tcp() {
+    let mongo = MongoEmbedded::new("7.0.2").unwrap().set_bind_ip("/tmp/mongo_test_cluster.sock");
+    let err = MongoCluster::start(&mongo, ReplicaSetConfig::new("rs0", 3)).await.err().expect("Unix sockets cannot be members");
+    assert!(err.to_string().contains("TCP"), "Unexpected error: {}", err);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_members_get_own_ports_and_db_paths() {
+    let dir = work_dir("cluster_members");
+    let mongo = recording_embedded(&dir);
+
+    let config = ReplicaSetConfig::new("rs0", 2).set_arbiter(true);
+    let err = MongoCluster::start(&mongo, config).await.err().expect("A fake mongod cannot be initiated");
+    assert!(err.to_string().contains("replSetInitiate"), "Unexpected error: {}", err);
+
+    let mut ports = Vec::new();
+    for member in ["member0", "member1", "arbiter"] {
+        let args = recorded_args(&dir.join("db").join(member));
+        assert!(args.contains("--replSet rs0"), "Unexpected arguments: {}", args);
+        let port: u16 = args.split_whitespace().nth(1).unwrap().parse().unwrap();
+        assert_ne!(port, 0);
+        ports.push(port);
+    }
+    ports.sort();
+    ports.dedup();
+    assert_eq!(ports.len(), 3, "Members must not share a port");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_members_share_keyfile() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = work_dir("cluster_keyfile");
+    let mongo = recording_embedded(&dir).set_credentials("admin", "secret");
+
+    let config = ReplicaSetConfig::new("rs0", 2).set_arbiter(true);
+    MongoCluster::start(&mongo, config).await.err().expect("A fake mongod cannot be initiated");
+
+    let key_file = dir.join("db/keyfile");
+    assert!(key_file.is_file());
+    assert_eq!(key_file.metadata().unwrap().permissions().mode() & 0o777, 0o400);
+    for member in ["member0", "member1", "arbiter"] {
+        let args = recorded_args(&dir.join("db").join(member));
+        assert!(args.contains(&format!("--keyFile {}", key_file.display())), "Unexpected arguments: {}", args);
+        assert!(!dir.join("db").join(member).join("keyfile").exists(), "Members must share one keyfile");
+    }
+}
@@ -0,0 +1,270 @@
+// Shared test fixtures: a minimal HTTP/1.1 server for exercising the
+// downloader without network access, and fake mongod binaries.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Clone, Default)]
+pub struct Route {
+    pub body: Vec<u8>,
+    pub etag: Option<String>,
+    // Honour `Range` requests
+    pub ranges: bool,
+    // Answer this many requests with 503 before serving the body
+    pub fail_first: usize,
+    // Close the connection after this many body bytes on the first response
+    pub cut_first_response_at: Option<usize>,
+    // Keep the first response open without sending more data for this long
+    pub stall_first_response: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Request {
+    pub path: String,
+    pub headers: HashMap<String, String>,
+}
+
+pub struct TestServer {
+    port: u16,
+    routes: Arc<Mutex<HashMap<String, Route>>>,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl TestServer {
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let routes: Arc<Mutex<HashMap<String, Route>>> = Arc::default();
+        let requests: Arc<Mutex<Vec<Request>>> = Arc::default();
+
+        let (thread_routes, thread_requests) = (routes.clone(), requests.clone());
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let routes = thread_routes.clone();
+                let requests = thread_requests.clone();
+                std::thread::spawn(move || handle(stream, routes, requests));
+            }
+        });
+
+        Self { port, routes, requests }
+    }
+
+    pub fn route(&self, path: &str, route: Route) {
+        self.routes.lock().unwrap().insert(path.to_string(), route);
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, path)
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+fn handle(stream: TcpStream, routes: Arc<Mutex<HashMap<String, Route>>>, requests: Arc<Mutex<Vec<Request>>>) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let path = request_line.split_whitespace().nth(1).unwrap_or("/").to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let previous_requests = {
+        let mut requests = requests.lock().unwrap();
+        let count = requests.iter().filter(|r| r.path == path).count();
+        requests.push(Request { path: path.clone(), headers: headers.clone() });
+        count
+    };
+
+    let mut stream = stream;
+    let route = match routes.lock().unwrap().get(&path).cloned() {
+        Some(route) => route,
+        None => {
+            let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+            return;
+        }
+    };
+
+    if previous_requests < route.fail_first {
+        let _ = stream.write_all(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        return;
+    }
+    let first_response = previous_requests == route.fail_first;
+
+    let total = route.body.len();
+    let mut start = 0;
+    let mut status = "200 OK";
+    let range = headers.get("range").and_then(|r| r.strip_prefix("bytes=")).and_then(|r| r.strip_suffix('-'));
+    let if_range_ok = match (headers.get("if-range"), &route.etag) {
+        (Some(given), Some(etag)) => given == etag,
+        (Some(_), None) => false,
+        (None, _) => true,
+    };
+    if let (true, Some(offset), true) = (route.ranges, range, if_range_ok) {
+        start = offset.parse().unwrap();
+        status = "206 Partial Content";
+    }
+
+    let mut head = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n", status, total - start);
+    if start > 0 {
+        head.push_str(&format!("Content-Range: bytes {}-{}/{}\r\n", start, total - 1, total));
+    }
+    if route.ranges {
+        head.push_str("Accept-Ranges: bytes\r\n");
+    }
+    if let Some(etag) = &route.etag {
+        head.push_str(&format!("ETag: {}\r\n", etag));
+    }
+    head.push_str("\r\n");
+
+    let mut end = total;
+    if let (true, Some(cut)) = (first_response, route.cut_first_response_at) {
+        end = cut.min(total);
+    }
+
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(&route.body[start.min(end)..end]);
+    let _ = stream.flush();
+
+    if let (true, Some(stall)) = (first_response, route.stall_first_response) {
+        std::thread::sleep(stall);
+    }
+}
+
+/// An empty `mongo_test_<name>` directory in the system temp dir.
+pub fn work_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mongo_test_{}", name));
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// "Waiting for connections", the line `ReadinessStrategy::LogEvent` waits for.
+pub const WAITING: &str = r#"{"t":{"$date":"2024-05-02T09:14:03.512+00:00"},"s":"I","c":"NETWORK","id":23016,"ctx":"listener","msg":"Waiting for connections","attr":{"port":27017}}"#;
+
+/// Answers the MongoDB wire protocol on `listener` like a healthy mongod
+/// with no databases: every `hello` reports a standalone primary and every
+/// other command succeeds.
+pub fn fake_mongo_server(listener: TcpListener) {
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { break };
+            std::thread::spawn(move || serve_mongo(stream));
+        }
+    });
+}
+
+fn serve_mongo(mut stream: TcpStream) {
+    use mongodb::bson::{doc, DateTime, Document};
+    use std::io::Read;
+
+    const OP_MSG: i32 = 2013;
+    loop {
+        let mut header = [0u8; 16];
+        if stream.read_exact(&mut header).is_err() {
+            return;
+        }
+        let int = |at: usize| i32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+        let mut body = vec![0u8; int(0) as usize - header.len()];
+        if stream.read_exact(&mut body).is_err() {
+            return;
+        }
+        // Flag bits, then a single kind 0 section holding the command
+        let command = Document::from_reader(&body[5..]).unwrap();
+        let reply = match command.keys().next().map(String::as_str) {
+            Some("hello" | "isMaster" | "ismaster") => doc! {
+                "ismaster": true,
+                "isWritablePrimary": true,
+                "helloOk": true,
+                "maxBsonObjectSize": 16 * 1024 * 1024,
+                "maxMessageSizeBytes": 48_000_000,
+                "maxWriteBatchSize": 100_000,
+                "localTime": DateTime::now(),
+                "logicalSessionTimeoutMinutes": 30,
+                "minWireVersion": 0,
+                "maxWireVersion": 17,
+                "ok": 1.0,
+            },
+            Some("listDatabases") => doc! { "databases": [], "totalSize": 0, "ok": 1.0 },
+            _ => doc! { "ok": 1.0 },
+        };
+
+        let mut payload = Vec::new();
+        reply.to_writer(&mut payload).unwrap();
+        let length = 16 + 4 + 1 + payload.len() as i32;
+        let mut message = Vec::new();
+        message.extend_from_slice(&length.to_le_bytes());
+        message.extend_from_slice(&0i32.to_le_bytes());
+        message.extend_from_slice(&int(4).to_le_bytes());
+        message.extend_from_slice(&OP_MSG.to_le_bytes());
+        message.extend_from_slice(&0u32.to_le_bytes());
+        message.push(0);
+        message.extend_from_slice(&payload);
+        if stream.write_all(&message).is_err() {
+            return;
+        }
+    }
+}
+
+/// Installs `script` as an executable `mongodb/bin/<name>` shell script in
+/// `dir` and returns `dir/mongodb`. mongod gets `--port` as `$2` and
+/// `--dbpath` as `$4`.
+#[cfg(unix)]
+pub fn fake_binary(dir: &Path, name: &str, script: &str) -> PathBuf {
+    use std::os::unix::fs::PermissionsExt;
+
+    let root = dir.join("mongodb");
+    std::fs::create_dir_all(root.join("bin")).unwrap();
+    let path = root.join("bin").join(name);
+    std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    root
+}
+
+#[cfg(unix)]
+pub fn fake_mongod(dir: &Path, script: &str) -> PathBuf {
+    fake_binary(dir, "mongod", script)
+}
+
+/// A mongod that writes its arguments to `<db path>/args` and reports
+/// readiness, but never answers a connection, so nothing can be initiated.
+pub fn recording_script() -> String {
+    format!("mkdir -p \"$4\"\necho \"$@\" > \"$4/args\"\necho '{}'\nwhile true; do sleep 0.05; done", WAITING)
+}
+
+/// The arguments a `recording_script` mongod was started with.
+pub fn recorded_args(db_path: &Path) -> String {
+    std::fs::read_to_string(db_path.join("args")).unwrap()
+}
+
+/// Settings that run a `recording_script` mongod from `dir`, with data in
+/// `dir/db`. Replica sets time out after a second, since nothing answers.
+#[cfg(unix)]
+pub fn recording_embedded(dir: &Path) -> mongo_embedded::MongoEmbedded {
+    use mongo_embedded::{MongoEmbedded, OfflineSource, ReadinessStrategy};
+
+    MongoEmbedded::new("7.0.2").unwrap()
+        .set_offline(OfflineSource::Directory(fake_mongod(dir, &recording_script())))
+        .set_db_path(dir.join("db"))
+        .set_readiness_strategy(ReadinessStrategy::LogEvent)
+        .set_startup_timeout(std::time::Duration::from_secs(1))
+}
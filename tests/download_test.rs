@@ -0,0 +1,323 @@
+mod common;
+
+use common::{Route, TestServer};
+use mongo_embedded::downloader::{
+    download_file_with_callback, download_file_with_retry, download_from_mirrors, fetch_sha256_from_mirrors,
+    get_download_url_for, Arch, DownloadEvent, DownloadTimeout, MongoUrl, Os, RetryPolicy, DEFAULT_BASE_URL,
+};
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn archive_body() -> Vec<u8> {
+    (0..200_000u32).map(|i| (i % 251) as u8).collect()
+}
+
+fn download_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mongo_test_download_{}", name));
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[tokio::test]
+async fn test_resume_with_range_request() {
+    let server = TestServer::start();
+    let body = archive_body();
+    server.route("/archive.tgz", Route {
+        body: body.clone(),
+        etag: Some("\"v1\"".to_string()),
+        ranges: true,
+        cut_first_response_at: Some(50_000),
+        ..Route::default()
+    });
+
+    let destination = download_dir("resume").join("archive.tgz");
+    let url = server.url("/archive.tgz");
+
+    // The first attempt is cut off half way and leaves a .part file behind
+    assert!(download_file_with_callback(&url, &destination, |_| {}).await.is_err());
+    assert!(!destination.exists());
+
+    let mut progress = Vec::new();
+    download_file_with_callback(&url, &destination, |p| progress.push((p.downloaded, p.total, p.resumed_from)))
+        .await
+        .expect("Resumed download failed");
+
+    assert_eq!(std::fs::read(&destination).unwrap(), body);
+    let (downloaded, total, resumed_from) = *progress.last().unwrap();
+    assert_eq!(resumed_from, 50_000);
+    assert_eq!(downloaded, body.len() as u64);
+    assert_eq!(total, Some(body.len() as u64));
+    assert!(progress.iter().all(|(d, _, _)| *d > 50_000));
+
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].headers.get("range").map(String::as_str), Some("bytes=50000-"));
+    assert_eq!(requests[1].headers.get("if-range").map(String::as_str), Some("\"v1\""));
+}
+
+#[tokio::test]
+async fn test_full_download_when_ranges_unsupported() {
+    let server = TestServer::start();
+    let body = archive_body();
+    server.route("/archive.tgz", Route {
+        body: body.clone(),
+        etag: Some("\"v1\"".to_string()),
+        ranges: false,
+        cut_first_response_at: Some(50_000),
+        ..Route::default()
+    });
+
+    let destination = download_dir("no_range").join("archive.tgz");
+    let url = server.url("/archive.tgz");
+
+    assert!(download_file_with_callback(&url, &destination, |_| {}).await.is_err());
+
+    let mut resumed = Vec::new();
+    download_file_with_callback(&url, &destination, |p| resumed.push(p.resumed_from))
+        .await
+        .expect("Full download failed");
+
+    assert_eq!(std::fs::read(&destination).unwrap(), body);
+    assert!(resumed.iter().all(|r| *r == 0));
+}
+
+#[tokio::test]
+async fn test_changed_file_is_downloaded_again() {
+    let server = TestServer::start();
+    let old_body = archive_body();
+    server.route("/archive.tgz", Route {
+        body: old_body,
+        etag: Some("\"v1\"".to_string()),
+        ranges: true,
+        cut_first_response_at: Some(50_000),
+        ..Route::default()
+    });
+
+    let destination = download_dir("changed").join("archive.tgz");
+    let url = server.url("/archive.tgz");
+    assert!(download_file_with_callback(&url, &destination, |_| {}).await.is_err());
+
+    // A new upload changes the ETag, so If-Range makes the server send everything
+    let new_body: Vec<u8> = archive_body().into_iter().rev().collect();
+    server.route("/archive.tgz", Route {
+        body: new_body.clone(),
+        etag: Some("\"v2\"".to_string()),
+        ranges: true,
+        ..Route::default()
+    });
+
+    download_file_with_callback(&url, &destination, |_| {}).await.expect("Download failed");
+    assert_eq!(std::fs::read(&destination).unwrap(), new_body);
+}
+
+#[tokio::test]
+async fn test_http_error_is_reported() {
+    let server = TestServer::start();
+    let destination = download_dir("not_found").join("archive.tgz");
+
+    assert!(download_file_with_callback(&server.url("/missing.tgz"), &destination, |_| {}).await.is_err());
+    assert!(!destination.exists());
+}
+
+fn fast_retries(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(50),
+        stall_timeout: Some(Duration::from_millis(500)),
+        overall_timeout: None,
+    }
+}
+
+fn retry_attempts(events: &[DownloadEvent]) -> Vec<u32> {
+    events
+        .iter()
+        .filter_map(|e| match e {
+            DownloadEvent::Retrying(retry) => Some(retry.attempt),
+            _ => None,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_retry_after_server_errors() {
+    let server = TestServer::start();
+    let body = archive_body();
+    server.route("/archive.tgz", Route { body: body.clone(), fail_first: 2, ..Route::default() });
+
+    let destination = download_dir("retry").join("archive.tgz");
+    let mut events = Vec::new();
+    download_file_with_retry(&server.url("/archive.tgz"), &destination, &fast_retries(3), |e| events.push(e))
+        .await
+        .expect("Download should succeed on the third attempt");
+
+    assert_eq!(std::fs::read(&destination).unwrap(), body);
+    assert_eq!(retry_attempts(&events), vec![1, 2]);
+}
+
+#[tokio::test]
+async fn test_retry_gives_up_after_max_attempts() {
+    let server = TestServer::start();
+    server.route("/archive.tgz", Route { body: archive_body(), fail_first: 5, ..Route::default() });
+
+    let destination = download_dir("retry_exhausted").join("archive.tgz");
+    let mut events = Vec::new();
+    let result = download_file_with_retry(&server.url("/archive.tgz"), &destination, &fast_retries(2), |e| events.push(e)).await;
+
+    assert!(result.is_err());
+    assert_eq!(retry_attempts(&events), vec![1]);
+    assert_eq!(server.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn test_client_errors_are_not_retried() {
+    let server = TestServer::start();
+    let destination = download_dir("retry_not_found").join("archive.tgz");
+
+    let result = download_file_with_retry(&server.url("/missing.tgz"), &destination, &fast_retries(5), |_| {}).await;
+    assert!(result.is_err());
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_stalled_download_is_resumed() {
+    let server = TestServer::start();
+    let body = archive_body();
+    server.route("/archive.tgz", Route {
+        body: body.clone(),
+        etag: Some("\"v1\"".to_string()),
+        ranges: true,
+        cut_first_response_at: Some(50_000),
+        stall_first_response: Some(Duration::from_secs(5)),
+        ..Route::default()
+    });
+
+    let destination = download_dir("stall").join("archive.tgz");
+    let mut events = Vec::new();
+    download_file_with_retry(&server.url("/archive.tgz"), &destination, &fast_retries(3), |e| events.push(e))
+        .await
+        .expect("Download should recover from the stall");
+
+    assert_eq!(std::fs::read(&destination).unwrap(), body);
+    assert_eq!(retry_attempts(&events), vec![1]);
+    let resumed = events.iter().any(|e| matches!(e, DownloadEvent::Progress(p) if p.resumed_from == 50_000));
+    assert!(resumed, "The retry should resume from the partial file");
+}
+
+#[tokio::test]
+async fn test_overall_timeout() {
+    let server = TestServer::start();
+    server.route("/archive.tgz", Route {
+        body: archive_body(),
+        cut_first_response_at: Some(1_000),
+        stall_first_response: Some(Duration::from_secs(5)),
+        ..Route::default()
+    });
+
+    let destination = download_dir("overall_timeout").join("archive.tgz");
+    let policy = RetryPolicy {
+        stall_timeout: None,
+        overall_timeout: Some(Duration::from_millis(300)),
+        ..fast_retries(3)
+    };
+    let err = download_file_with_retry(&server.url("/archive.tgz"), &destination, &policy, |_| {})
+        .await
+        .unwrap_err();
+    assert!(matches!(err.downcast_ref::<DownloadTimeout>(), Some(DownloadTimeout::Overall(_))));
+}
+
+fn linux_archive() -> MongoUrl {
+    get_download_url_for("7.0.2", &Os::Linux, &Arch::X86_64, None).unwrap()
+}
+
+fn unreachable_mirror() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    drop(listener);
+    format!("http://127.0.0.1:{}", port)
+}
+
+fn file_url(path: &std::path::Path) -> String {
+    format!("file://{}", path.display())
+}
+
+#[tokio::test]
+async fn test_http_mirror_replaces_base_url() {
+    let server = TestServer::start();
+    let archive = linux_archive();
+    let body = archive_body();
+    server.route(&format!("/{}", archive.path), Route { body: body.clone(), ..Route::default() });
+
+    let destination = download_dir("mirror_http").join(&archive.filename);
+    download_from_mirrors(&[server.url("")], &archive, &destination, &fast_retries(1), |_| {})
+        .await
+        .expect("Download from mirror failed");
+
+    assert_eq!(std::fs::read(&destination).unwrap(), body);
+}
+
+#[tokio::test]
+async fn test_default_mirror_uses_archive_url() {
+    let server = TestServer::start();
+    let body = archive_body();
+    server.route("/releases/archive.tgz", Route { body: body.clone(), ..Route::default() });
+    // As resolved from a release catalog that lists another host
+    let archive = MongoUrl { url: server.url("/releases/archive.tgz"), ..linux_archive() };
+
+    let destination = download_dir("mirror_default").join(&archive.filename);
+    download_from_mirrors(&[DEFAULT_BASE_URL.to_string()], &archive, &destination, &fast_retries(1), |_| {})
+        .await
+        .expect("Download from the archive URL failed");
+
+    assert_eq!(std::fs::read(&destination).unwrap(), body);
+}
+
+#[tokio::test]
+async fn test_falls_back_to_file_mirror() {
+    let archive = linux_archive();
+    let body = archive_body();
+
+    // A local directory with the same layout as fastdl.mongodb.org
+    let mirror = download_dir("mirror_file_source");
+    std::fs::create_dir_all(mirror.join("linux")).unwrap();
+    std::fs::write(mirror.join(&archive.path), &body).unwrap();
+    std::fs::write(mirror.join(format!("{}.sha256", archive.path)), "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824  archive\n").unwrap();
+
+    let mirrors = vec![unreachable_mirror(), file_url(&mirror)];
+    let destination = download_dir("mirror_file").join(&archive.filename);
+    download_from_mirrors(&mirrors, &archive, &destination, &fast_retries(2), |_| {})
+        .await
+        .expect("Download should fall back to the file mirror");
+    assert_eq!(std::fs::read(&destination).unwrap(), body);
+
+    let digest = fetch_sha256_from_mirrors(&mirrors, &archive).await.unwrap();
+    assert_eq!(digest, "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824");
+}
+
+#[tokio::test]
+async fn test_flat_file_mirror() {
+    let archive = linux_archive();
+    let mirror = download_dir("mirror_flat_source");
+    std::fs::write(mirror.join(&archive.filename), b"archive").unwrap();
+
+    let destination = download_dir("mirror_flat").join(&archive.filename);
+    download_from_mirrors(&[file_url(&mirror)], &archive, &destination, &fast_retries(1), |_| {})
+        .await
+        .expect("Download from flat directory failed");
+    assert_eq!(std::fs::read(&destination).unwrap(), b"archive");
+}
+
+#[tokio::test]
+async fn test_all_mirrors_failing() {
+    let archive = linux_archive();
+    let missing = download_dir("mirror_missing");
+    let destination = download_dir("mirror_none").join(&archive.filename);
+
+    let err = download_from_mirrors(&[file_url(&missing)], &archive, &destination, &fast_retries(1), |_| {})
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("any mirror"));
+}
@@ -0,0 +1,280 @@
+use mongo_embedded::extractor::{
+    extract, extract_binaries, extract_tar_from_reader, ArchiveFormat, ExtractError, UnsupportedFormat,
+};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+fn work_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mongo_test_extract_{}", name));
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+enum TarEntry<'a> {
+    File(&'a str, &'a [u8]),
+    Symlink(&'a str, &'a str),
+    HardLink(&'a str, &'a str),
+}
+
+// Writes names straight into the header so that paths `tar::Builder` would
+// refuse, like `../x`, end up in the archive.
+fn build_tgz(path: &Path, entries: &[TarEntry]) {
+    let file = std::fs::File::create(path).unwrap();
+    let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::fast());
+    let mut builder = tar::Builder::new(encoder);
+    for entry in entries {
+        let mut header = tar::Header::new_old();
+        let (name, data, link, kind): (&str, &[u8], Option<&str>, tar::EntryType) = match entry {
+            TarEntry::File(name, data) => (name, data, None, tar::EntryType::Regular),
+            TarEntry::Symlink(name, target) => (name, b"", Some(target), tar::EntryType::Symlink),
+            TarEntry::HardLink(name, target) => (name, b"", Some(target), tar::EntryType::Link),
+        };
+        header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+        if let Some(link) = link {
+            header.as_old_mut().linkname[..link.len()].copy_from_slice(link.as_bytes());
+        }
+        header.set_entry_type(kind);
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, data).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap();
+}
+
+fn build_zip(path: &Path, files: &[(&str, &[u8])], symlinks: &[(&str, &str)]) {
+    let file = std::fs::File::create(path).unwrap();
+    let mut writer = zip::ZipWriter::new(file);
+    let options = zip::write::SimpleFileOptions::default();
+    for (name, data) in files {
+        writer.start_file(*name, options).unwrap();
+        writer.write_all(data).unwrap();
+    }
+    for (name, target) in symlinks {
+        writer.add_symlink(*name, *target, options).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+fn expect_unsafe(archive: &Path, target: &Path) -> String {
+    let err = extract(archive, target).expect_err("Malicious archive should be rejected");
+    err.downcast_ref::<ExtractError>()
+        .unwrap_or_else(|| panic!("Expected an ExtractError, got: {}", err))
+        .entry()
+        .to_string()
+}
+
+#[test]
+fn test_tgz_parent_traversal_rejected() {
+    let dir = work_dir("tgz_parent");
+    let archive = dir.join("evil.tgz");
+    build_tgz(&archive, &[TarEntry::File("ok.txt", b"ok"), TarEntry::File("../evil.txt", b"pwned")]);
+
+    assert_eq!(expect_unsafe(&archive, &dir.join("out")), "../evil.txt");
+    assert!(!dir.join("evil.txt").exists());
+}
+
+#[test]
+fn test_tgz_absolute_path_rejected() {
+    let dir = work_dir("tgz_absolute");
+    let archive = dir.join("evil.tgz");
+    let absolute = dir.join("absolute.txt");
+    build_tgz(&archive, &[TarEntry::File(absolute.to_str().unwrap(), b"pwned")]);
+
+    expect_unsafe(&archive, &dir.join("out"));
+    assert!(!absolute.exists());
+}
+
+#[test]
+fn test_tgz_escaping_links_rejected() {
+    let dir = work_dir("tgz_links");
+    let archive = dir.join("symlink.tgz");
+    build_tgz(&archive, &[TarEntry::Symlink("bin/escape", "../../outside")]);
+    assert_eq!(expect_unsafe(&archive, &dir.join("out")), "bin/escape");
+
+    let archive = dir.join("hardlink.tgz");
+    build_tgz(&archive, &[TarEntry::HardLink("passwd", "/etc/passwd")]);
+    assert_eq!(expect_unsafe(&archive, &dir.join("out2")), "passwd");
+}
+
+#[test]
+fn test_tgz_write_through_symlink_rejected() {
+    let dir = work_dir("tgz_through_symlink");
+    let archive = dir.join("evil.tgz");
+    // Each link looks harmless on its own
+    build_tgz(&archive, &[TarEntry::Symlink("a", "."), TarEntry::Symlink("a/b", ".."), TarEntry::File("a/b/evil.txt", b"pwned")]);
+
+    assert_eq!(expect_unsafe(&archive, &dir.join("out")), "a/b");
+    assert!(!dir.join("evil.txt").exists());
+}
+
+#[test]
+fn test_tgz_safe_archive_extracts() {
+    let dir = work_dir("tgz_safe");
+    let archive = dir.join("mongodb.tgz");
+    build_tgz(&archive, &[
+        TarEntry::File("./mongodb-7.0.2/bin/mongod", b"binary"),
+        TarEntry::Symlink("mongodb-7.0.2/mongod", "bin/mongod"),
+    ]);
+
+    let out = dir.join("out");
+    extract(&archive, &out).unwrap();
+    assert_eq!(std::fs::read(out.join("mongodb-7.0.2/bin/mongod")).unwrap(), b"binary");
+    #[cfg(unix)]
+    assert_eq!(std::fs::read(out.join("mongodb-7.0.2/mongod")).unwrap(), b"binary");
+}
+
+#[test]
+fn test_zip_parent_traversal_rejected() {
+    let dir = work_dir("zip_parent");
+    let archive = dir.join("evil.zip");
+    build_zip(&archive, &[("ok.txt", b"ok"), ("../evil.txt", b"pwned")], &[]);
+
+    assert_eq!(expect_unsafe(&archive, &dir.join("out")), "../evil.txt");
+    assert!(!dir.join("evil.txt").exists());
+}
+
+#[test]
+fn test_zip_escaping_symlink_rejected() {
+    let dir = work_dir("zip_symlink");
+    let archive = dir.join("evil.zip");
+    build_zip(&archive, &[], &[("bin/escape", "../../../etc")]);
+
+    assert_eq!(expect_unsafe(&archive, &dir.join("out")), "bin/escape");
+}
+
+#[test]
+fn test_zip_safe_archive_extracts() {
+    let dir = work_dir("zip_safe");
+    let archive = dir.join("mongodb.zip");
+    build_zip(&archive, &[("mongodb-7.0.2/bin/mongod.exe", b"binary")], &[]);
+
+    let out = dir.join("out");
+    extract(&archive, &out).unwrap();
+    assert_eq!(std::fs::read(out.join("mongodb-7.0.2/bin/mongod.exe")).unwrap(), b"binary");
+}
+
+fn names(binaries: &[&str]) -> Vec<String> {
+    binaries.iter().map(|b| b.to_string()).collect()
+}
+
+#[test]
+fn test_selective_tgz_extraction() {
+    let dir = work_dir("selective_tgz");
+    let archive = dir.join("mongodb.tgz");
+    build_tgz(&archive, &[
+        TarEntry::File("mongodb-7.0.2/LICENSE-Community.txt", b"license"),
+        TarEntry::File("mongodb-7.0.2/bin/mongod", b"mongod"),
+        TarEntry::File("mongodb-7.0.2/bin/mongos", b"mongos"),
+        TarEntry::File("mongodb-7.0.2/bin/install_compass", b"script"),
+    ]);
+
+    let out = dir.join("out");
+    extract_binaries(&archive, &out, &names(&["mongod", "mongos"])).unwrap();
+
+    let mut extracted: Vec<String> = std::fs::read_dir(out.join("bin"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    extracted.sort();
+    assert_eq!(extracted, vec!["mongod", "mongos"]);
+    assert_eq!(std::fs::read_dir(&out).unwrap().count(), 1, "Only bin/ should be created");
+    assert_eq!(std::fs::read(out.join("bin/mongod")).unwrap(), b"mongod");
+}
+
+#[test]
+fn test_selective_zip_extraction() {
+    let dir = work_dir("selective_zip");
+    let archive = dir.join("mongodb.zip");
+    build_zip(&archive, &[("mongodb-7.0.2/README", b"readme"), ("mongodb-7.0.2/bin/mongod.exe", b"mongod")], &[]);
+
+    let out = dir.join("out");
+    extract_binaries(&archive, &out, &names(&["mongod.exe"])).unwrap();
+    assert_eq!(std::fs::read(out.join("bin/mongod.exe")).unwrap(), b"mongod");
+    assert!(!out.join("mongodb-7.0.2").exists());
+}
+
+#[test]
+fn test_selective_extraction_missing_binary() {
+    let dir = work_dir("selective_missing");
+    let archive = dir.join("mongodb.tgz");
+    build_tgz(&archive, &[TarEntry::File("mongodb-7.0.2/bin/mongod", b"mongod")]);
+
+    let err = extract_binaries(&archive, &dir.join("out"), &names(&["mongod", "mongodump"])).unwrap_err();
+    assert!(err.to_string().contains("bin/mongodump"), "Unexpected error: {}", err);
+}
+
+fn build_tar_xz(path: &Path, files: &[(&str, &[u8])]) {
+    let file = std::fs::File::create(path).unwrap();
+    let writer = lzma_rust2::XzWriter::new(file, lzma_rust2::XzOptions::with_preset(1)).unwrap();
+    let mut builder = tar::Builder::new(writer);
+    for (name, data) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o755);
+        builder.append_data(&mut header, name, *data).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap();
+}
+
+#[test]
+fn test_tar_xz_extracts() {
+    let dir = work_dir("tar_xz");
+    let archive = dir.join("mongosh-2.3.0-linux-x64.tar.xz");
+    build_tar_xz(&archive, &[("mongosh-2.3.0-linux-x64/bin/mongosh", b"mongosh")]);
+
+    assert_eq!(ArchiveFormat::detect(&archive).unwrap(), ArchiveFormat::TarXz);
+    let out = dir.join("out");
+    extract(&archive, &out).unwrap();
+    assert_eq!(std::fs::read(out.join("mongosh-2.3.0-linux-x64/bin/mongosh")).unwrap(), b"mongosh");
+}
+
+#[test]
+fn test_format_detected_by_content() {
+    let dir = work_dir("magic");
+    // Double extension, no extension and a misleading one
+    for name in ["mongodb-database-tools-100.9.0.tar.gz", "mongodb-download", "mislabelled.zip"] {
+        let archive = dir.join(name);
+        build_tgz(&archive, &[TarEntry::File("mongodb/bin/mongod", b"binary")]);
+        assert_eq!(ArchiveFormat::detect(&archive).unwrap(), ArchiveFormat::TarGz);
+
+        let out = dir.join(format!("{}.out", name));
+        extract(&archive, &out).unwrap();
+        assert_eq!(std::fs::read(out.join("mongodb/bin/mongod")).unwrap(), b"binary");
+    }
+
+    let archive = dir.join("windows.tgz");
+    build_zip(&archive, &[("mongodb/bin/mongod.exe", b"binary")], &[]);
+    assert_eq!(ArchiveFormat::detect(&archive).unwrap(), ArchiveFormat::Zip);
+}
+
+#[test]
+fn test_unsupported_format() {
+    let dir = work_dir("unsupported");
+    let archive = dir.join("mongodb.tgz");
+    std::fs::write(&archive, b"<html>Not Found</html>").unwrap();
+
+    let err = extract(&archive, &dir.join("out")).unwrap_err();
+    let unsupported = err.downcast_ref::<UnsupportedFormat>().expect("Expected an UnsupportedFormat error");
+    assert_eq!(unsupported.source, archive.display().to_string());
+}
+
+#[test]
+fn test_tar_xz_from_reader() {
+    let dir = work_dir("xz_reader");
+    let archive = dir.join("tools.tar.xz");
+    build_tar_xz(&archive, &[("tools/bin/mongodump", b"mongodump")]);
+
+    let out = dir.join("out");
+    let reader = std::fs::File::open(&archive).unwrap();
+    extract_tar_from_reader(reader, "tools.tar.xz", &out, Some(&names(&["mongodump"]))).unwrap();
+    assert_eq!(std::fs::read(out.join("bin/mongodump")).unwrap(), b"mongodump");
+
+    let zip = dir.join("tools.zip");
+    build_zip(&zip, &[("tools/bin/mongodump", b"mongodump")], &[]);
+    let err = extract_tar_from_reader(std::fs::File::open(&zip).unwrap(), "tools.zip", &out, None).unwrap_err();
+    assert!(err.is::<UnsupportedFormat>());
+}
@@ -1,4 +1,4 @@
-use mongo_embedded::MongoEmbedded;
+use mongo_embedded::{MongoCluster, MongoEmbedded, MongoShardedCluster, ReplicaSetConfig, ShardedClusterConfig, ShutdownMethod};
 use tokio::time::{sleep, Duration};
 
 #[tokio::test]
@@ -68,3 +68,174 @@ async fn test_socket_bind() {
         std::fs::remove_file(&socket_path).unwrap();
     }
 }
+
+#[tokio::test]
+async fn test_graceful_shutdown() {
+    let temp_dir = std::env::temp_dir().join("mongo_test_db_shutdown");
+    if temp_dir.exists() {
+        std::fs::remove_dir_all(&temp_dir).unwrap();
+    }
+    let mongo = MongoEmbedded::new("7.0.2").unwrap()
+        .set_credentials("test", "test")
+        .set_port(12347)
+        .set_db_path(temp_dir.clone());
+
+    let mut process = mongo.start().await.expect("Failed to start MongoDB");
+    let method = process.shutdown(Duration::from_secs(10)).await.expect("Failed to stop MongoDB");
+    assert_eq!(method, ShutdownMethod::Command);
+
+    // A clean shutdown empties the lock file
+    let lock = std::fs::read(temp_dir.join("mongod.lock")).unwrap();
+    assert!(lock.is_empty(), "mongod.lock should be empty after a clean shutdown");
+}
+
+#[tokio::test]
+async fn test_replica_set_transactions_and_change_streams() {
+    use futures_util::StreamExt;
+    use mongodb::bson::{doc, Document};
+
+    let temp_dir = std::env::temp_dir().join("mongo_test_db_replset");
+    if temp_dir.exists() {
+        std::fs::remove_dir_all(&temp_dir).unwrap();
+    }
+    let mongo = MongoEmbedded::new("7.0.2").unwrap()
+        .set_port_auto()
+        .set_db_path(temp_dir)
+        .set_replica_set("rs0");
+
+    let process = mongo.start().await.expect("Failed to start replica set");
+    assert!(process.connection_string.ends_with("replicaSet=rs0"));
+
+    let client = mongodb::Client::with_uri_str(&process.connection_string).await.unwrap();
+    let items = client.database("test").collection::<Document>("items");
+    let mut changes = items.watch(None, None).await.expect("Change streams need a replica set");
+
+    let mut session = client.start_session(None).await.unwrap();
+    session.start_transaction(None).await.unwrap();
+    items.insert_one_with_session(doc! { "name": "a" }, None, &mut session).await.unwrap();
+    items.insert_one_with_session(doc! { "name": "b" }, None, &mut session).await.unwrap();
+    session.commit_transaction().await.expect("Transactions need a replica set");
+
+    let change = changes.next().await.unwrap().unwrap();
+    assert_eq!(change.full_document.unwrap().get_str("name").unwrap(), "a");
+    assert_eq!(items.count_documents(None, None).await.unwrap(), 2);
+}
+
+#[tokio::test]
+async fn test_cluster_failover() {
+    use mongodb::bson::{doc, Document};
+
+    let temp_dir = std::env::temp_dir().join("mongo_test_db_cluster");
+    if temp_dir.exists() {
+        std::fs::remove_dir_all(&temp_dir).unwrap();
+    }
+    let mongo = MongoEmbedded::new("7.0.2").unwrap().set_db_path(temp_dir);
+    let mut cluster = MongoCluster::start(&mongo, ReplicaSetConfig::new("rs0", 3))
+        .await
+        .expect("Failed to start replica set");
+
+    let client = mongodb::Client::with_uri_str(&cluster.connection_string).await.unwrap();
+    let items = client.database("test").collection::<Document>("items");
+    items.insert_one(doc! { "n": 1 }, None).await.unwrap();
+
+    let old = cluster.step_down_primary().await.unwrap();
+    let new = cluster.wait_for_primary(Duration::from_secs(30)).await.unwrap();
+    assert_ne!(old, new);
+    items.insert_one(doc! { "n": 2 }, None).await.expect("Retryable write after step down");
+
+    cluster.kill_member(new).unwrap();
+    let after_kill = cluster.wait_for_primary(Duration::from_secs(30)).await.unwrap();
+    assert_ne!(after_kill, new);
+    items.insert_one(doc! { "n": 3 }, None).await.expect("Retryable write after failover");
+
+    cluster.restart_member(new).await.unwrap();
+    assert!(cluster.members[new].process.is_some());
+    assert_eq!(items.count_documents(None, None).await.unwrap(), 3);
+
+    cluster.shutdown(Duration::from_secs(10)).await.unwrap();
+    assert!(cluster.members.iter().all(|m| m.process.is_none()));
+}
+
+#[tokio::test]
+async fn test_sharded_cluster() {
+    use mongodb::bson::{doc, Document};
+
+    let temp_dir = std::env::temp_dir().join("mongo_test_db_sharded");
+    if temp_dir.exists() {
+        std::fs::remove_dir_all(&temp_dir).unwrap();
+    }
+    let mongo = MongoEmbedded::new("7.0.2").unwrap().set_db_path(temp_dir);
+    let mut cluster = MongoShardedCluster::start(&mongo, ShardedClusterConfig::new(2))
+        .await
+        .expect("Failed to start sharded cluster");
+
+    cluster.enable_sharding("test").await.unwrap();
+    cluster.shard_collection("test.items", doc! { "n": "hashed" }).await.unwrap();
+
+    let client = mongodb::Client::with_uri_str(&cluster.connection_string).await.unwrap();
+    let shards = client.database("config").collection::<Document>("shards");
+    assert_eq!(shards.count_documents(None, None).await.unwrap(), 2);
+
+    let items = client.database("test").collection::<Document>("items");
+    let docs: Vec<Document> = (0..100).map(|n| doc! { "n": n }).collect();
+    items.insert_many(docs, None).await.unwrap();
+    assert_eq!(items.count_documents(None, None).await.unwrap(), 100);
+
+    cluster.shutdown(Duration::from_secs(10)).await.unwrap();
+    assert!(cluster.routers.is_empty());
+}
+
+#[tokio::test]
+async fn test_replica_set_with_auth() {
+    use mongodb::bson::{doc, Document};
+
+    let temp_dir = std::env::temp_dir().join("mongo_test_db_cluster_auth");
+    if temp_dir.exists() {
+        std::fs::remove_dir_all(&temp_dir).unwrap();
+    }
+    let mongo = MongoEmbedded::new("7.0.2").unwrap()
+        .set_db_path(temp_dir)
+        .set_credentials("test", "test");
+    let cluster = MongoCluster::start(&mongo, ReplicaSetConfig::new("rs0", 3))
+        .await
+        .expect("Failed to start replica set with auth");
+    assert!(cluster.connection_string.starts_with("mongodb://test:test@"));
+
+    let client = mongodb::Client::with_uri_str(&cluster.connection_string).await.unwrap();
+    let items = client.database("test").collection::<Document>("items");
+    items.insert_one(doc! { "n": 1 }, None).await.expect("Authenticated write");
+
+    // Without credentials the set refuses to serve data
+    let anonymous = cluster.connection_string.replace("test:test@", "");
+    let client = mongodb::Client::with_uri_str(&anonymous).await.unwrap();
+    let items = client.database("test").collection::<Document>("items");
+    assert!(items.count_documents(None, None).await.is_err());
+
+    cluster.step_down_primary().await.expect("Stepping down needs the credentials");
+}
+
+#[tokio::test]
+async fn test_tls_with_generated_certificates() {
+    use mongo_embedded::TlsOptions;
+    use mongodb::bson::doc;
+
+    let temp_dir = std::env::temp_dir().join("mongo_test_db_tls");
+    if temp_dir.exists() {
+        std::fs::remove_dir_all(&temp_dir).unwrap();
+    }
+    let mongo = MongoEmbedded::new("7.0.2").unwrap()
+        .set_port_auto()
+        .set_db_path(temp_dir)
+        .set_tls(TlsOptions::Generated);
+
+    let process = mongo.start().await.expect("Failed to start MongoDB with TLS");
+    assert!(process.connection_string.contains("tls=true"));
+
+    let client = mongodb::Client::with_uri_str(&process.connection_string).await.unwrap();
+    client.database("admin").run_command(doc! { "ping": 1 }, None).await.expect("TLS connection");
+
+    // A plain connection is refused
+    let plain = format!("mongodb://127.0.0.1:{}/?serverSelectionTimeoutMS=2000", process.port);
+    let client = mongodb::Client::with_uri_str(&plain).await.unwrap();
+    assert!(client.database("admin").run_command(doc! { "ping": 1 }, None).await.is_err());
+}
@@ -0,0 +1,46 @@
+use mongo_embedded::{LogEntry, Severity};
+
+const WAITING: &str = r#"{"t":{"$date":"2024-05-02T09:14:03.512+00:00"},"s":"I",  "c":"NETWORK",  "id":23016,   "ctx":"listener","msg":"Waiting for connections","attr":{"port":27017,"ssl":"off"}}"#;
+const SLOW_QUERY: &str = r#"{"t":{"$date":"2024-05-02T09:15:10.001+00:00"},"s":"I",  "c":"COMMAND",  "id":51803,   "ctx":"conn7","msg":"Slow query","attr":{"type":"command","ns":"test.items","durationMillis":153}}"#;
+
+#[test]
+fn test_parse_entry() {
+    let entry = LogEntry::parse(WAITING).unwrap();
+    assert_eq!(entry.timestamp, "2024-05-02T09:14:03.512+00:00");
+    assert_eq!(entry.severity, Severity::Info);
+    assert_eq!(entry.component, "NETWORK");
+    assert_eq!(entry.id, 23016);
+    assert_eq!(entry.context, "listener");
+    assert_eq!(entry.message, "Waiting for connections");
+    assert_eq!(entry.attr("port").and_then(|p| p.as_u64()), Some(27017));
+
+    let slow = LogEntry::parse(SLOW_QUERY).unwrap();
+    assert_eq!(slow.attr("durationMillis").and_then(|d| d.as_u64()), Some(153));
+    assert_eq!(slow.attr("missing"), None);
+}
+
+#[test]
+fn test_parse_severities() {
+    let line = |s: &str| WAITING.replace(r#""s":"I""#, &format!(r#""s":"{}""#, s));
+    assert_eq!(LogEntry::parse(&line("F")).unwrap().severity, Severity::Fatal);
+    assert_eq!(LogEntry::parse(&line("E")).unwrap().severity, Severity::Error);
+    assert_eq!(LogEntry::parse(&line("W")).unwrap().severity, Severity::Warning);
+    assert_eq!(LogEntry::parse(&line("D2")).unwrap().severity, Severity::Debug(2));
+    assert!(Severity::Error < Severity::Warning);
+    assert!(LogEntry::parse(&line("X")).is_none());
+}
+
+#[test]
+fn test_entry_without_attributes() {
+    let line = r#"{"t":{"$date":"2024-05-02T09:14:03.000+00:00"},"s":"I","c":"CONTROL","id":20698,"ctx":"-","msg":"***** SERVER RESTARTED *****"}"#;
+    let entry = LogEntry::parse(line).unwrap();
+    assert_eq!(entry.attr, serde_json::Value::Null);
+    assert_eq!(entry.attr("port"), None);
+}
+
+#[test]
+fn test_unstructured_lines_are_skipped() {
+    assert!(LogEntry::parse("about to fork child process, waiting until server is ready for connections.").is_none());
+    assert!(LogEntry::parse(r#"{"msg":"no other fields"}"#).is_none());
+    assert!(LogEntry::parse("").is_none());
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use common::work_dir;
+use mongo_embedded::cache::InstallMarker;
+use mongo_embedded::{InitStatus, MongoEmbedded, OfflineSource};
+
+#[tokio::test]
+async fn test_missing_archive_never_downloads() {
+    let dir = work_dir("offline_missing_archive");
+    let archive = dir.join("mongodb-linux-x86_64-ubuntu2204-7.0.2.tgz");
+
+    let mut mongo = MongoEmbedded::new("7.0.2").unwrap()
+        // An unreachable mirror makes any download attempt fail loudly
+        .set_download_base_url("http://127.0.0.1:1")
+        .set_offline(OfflineSource::Archive(archive.clone()));
+    mongo.extract_path = dir.join("extracted");
+
+    assert!(!mongo.is_installed());
+
+    let mut downloading = false;
+    let err = match mongo.start_with_progress(|status| {
+        if matches!(status, InitStatus::Downloading | InitStatus::DownloadProgress(_)) {
+            downloading = true;
+        }
+    }).await {
+        Ok(_) => panic!("Start should fail without the archive"),
+        Err(e) => e,
+    };
+
+    assert!(!downloading, "Offline mode must not download");
+    assert!(err.to_string().contains(archive.to_str().unwrap()), "Unexpected error: {}", err);
+}
+
+#[tokio::test]
+async fn test_directory_without_mongod() {
+    let dir = work_dir("offline_empty_dir");
+    let mongo = MongoEmbedded::new("7.0.2").unwrap()
+        .set_offline(OfflineSource::Directory(dir.clone()));
+
+    assert!(!mongo.is_installed());
+    let err = mongo.start().await.err().expect("Start should fail without mongod");
+    assert!(err.to_string().contains("offline directory"), "Unexpected error: {}", err);
+}
+
+#[test]
+fn test_directory_with_mongod_is_installed() {
+    let dir = work_dir("offline_provisioned");
+    std::fs::create_dir_all(dir.join("mongodb-7.0.2/bin")).unwrap();
+    let binary = if cfg!(windows) { "mongod.exe" } else { "mongod" };
+    std::fs::write(dir.join("mongodb-7.0.2/bin").join(binary), b"").unwrap();
+
+    let mongo = MongoEmbedded::new("7.0.2").unwrap()
+        .set_offline(OfflineSource::Directory(dir));
+    assert!(mongo.is_installed());
+}
+
+#[test]
+fn test_extracted_archive_is_installed() {
+    let dir = work_dir("offline_extracted");
+    let archive = dir.join("mongodb-linux-x86_64-ubuntu2204-7.0.2.tgz");
+    std::fs::write(&archive, b"").unwrap();
+    let mut mongo = MongoEmbedded::new("7.0.2").unwrap()
+        .set_offline(OfflineSource::Archive(archive.clone()));
+    mongo.extract_path = dir.join("extracted");
+    assert!(!mongo.is_installed(), "An archive that was never extracted is not installed");
+
+    let install = dir.join("extracted/7.0.2");
+    std::fs::create_dir_all(install.join("bin")).unwrap();
+    let binary = if cfg!(windows) { "mongod.exe" } else { "mongod" };
+    std::fs::write(install.join("bin").join(binary), b"").unwrap();
+    assert!(!mongo.is_installed(), "An extraction without its marker is incomplete");
+
+    InstallMarker {
+        version: "7.0.2".to_string(),
+        archive: archive.display().to_string(),
+        sha256: "0".repeat(64),
+        binaries: None,
+    }
+    .write(&install)
+    .unwrap();
+    std::fs::remove_file(&archive).unwrap();
+    assert!(mongo.is_installed(), "The extraction stays usable without the archive");
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_system_binary_left_untouched() {
+    use common::{fake_mongod, recording_script};
+    use mongo_embedded::ReadinessStrategy;
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::Duration;
+
+    let dir = work_dir("offline_system_path");
+    let bin = fake_mongod(&dir, &recording_script()).join("bin");
+    // Like a root-owned install: executable but not writable
+    std::fs::set_permissions(bin.join("mongod"), std::fs::Permissions::from_mode(0o555)).unwrap();
+
+    let mongo = MongoEmbedded::new("7.0.2").unwrap()
+        .set_offline(OfflineSource::SystemPath)
+        .set_search_path(vec![dir.join("empty"), bin.clone()])
+        .set_db_path(dir.join("db"))
+        .set_port_auto()
+        .set_readiness_strategy(ReadinessStrategy::LogEvent)
+        .set_startup_timeout(Duration::from_secs(1));
+
+    assert!(mongo.is_installed());
+    mongo.start().await.unwrap();
+    let mode = std::fs::metadata(bin.join("mongod")).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o555, "A binary we did not extract must not be changed");
+}
@@ -0,0 +1,67 @@
+use mongo_embedded::downloader::{get_download_url_for, Arch, Os};
+use mongo_embedded::platform::LinuxDistro;
+use std::path::PathBuf;
+
+fn fake_root(name: &str, os_release: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("mongo_test_os_release_{}", name));
+    if root.exists() {
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+    std::fs::create_dir_all(root.join("etc")).unwrap();
+    std::fs::write(root.join("etc/os-release"), os_release).unwrap();
+    root
+}
+
+#[test]
+fn test_detect_ubuntu() {
+    let root = fake_root("ubuntu2404", "NAME=\"Ubuntu\"\nID=ubuntu\nID_LIKE=debian\nVERSION_ID=\"24.04\"\nUBUNTU_CODENAME=noble\n");
+    let distro = LinuxDistro::detect_in(&root).unwrap();
+
+    assert_eq!(distro.id, "ubuntu");
+    assert_eq!(distro.version_id, "24.04");
+    assert_eq!(distro.select_target(&Arch::X86_64, "8.0.4").unwrap(), "ubuntu2404");
+    // 7.0 was never published for 24.04, so we fall back to the 22.04 build
+    assert_eq!(distro.select_target(&Arch::X86_64, "7.0.2").unwrap(), "ubuntu2204");
+}
+
+#[test]
+fn test_detect_debian_and_rhel_family() {
+    let debian = LinuxDistro::detect_in(&fake_root("debian12", "ID=debian\nVERSION_ID=\"12\"\n")).unwrap();
+    assert_eq!(debian.select_target(&Arch::X86_64, "7.0.5").unwrap(), "debian12");
+    assert_eq!(debian.select_target(&Arch::X86_64, "7.0.2").unwrap(), "ubuntu2204");
+
+    let rocky = LinuxDistro::parse("ID=\"rocky\"\nID_LIKE=\"rhel centos fedora\"\nVERSION_ID=\"9.3\"\n");
+    assert_eq!(rocky.select_target(&Arch::X86_64, "7.0.2").unwrap(), "rhel90");
+
+    let rhel8 = LinuxDistro::parse("ID=\"rhel\"\nID_LIKE=\"fedora\"\nVERSION_ID=\"8.9\"\n");
+    assert_eq!(rhel8.select_target(&Arch::X86_64, "7.0.2").unwrap(), "rhel80");
+    assert_eq!(rhel8.select_target(&Arch::Aarch64, "7.0.2").unwrap(), "rhel82");
+
+    let amazon = LinuxDistro::parse("ID=\"amzn\"\nID_LIKE=\"fedora\"\nVERSION_ID=\"2023\"\n");
+    assert_eq!(amazon.select_target(&Arch::X86_64, "7.0.2").unwrap(), "amazon2023");
+    assert_eq!(amazon.select_target(&Arch::X86_64, "6.0.12").unwrap(), "rhel90");
+}
+
+#[test]
+fn test_unpublished_target_is_an_error() {
+    let debian10 = LinuxDistro::parse("ID=debian\nVERSION_ID=\"10\"\n");
+    assert!(debian10.select_target(&Arch::X86_64, "7.0.2").is_err());
+}
+
+#[test]
+fn test_missing_os_release() {
+    let root = std::env::temp_dir().join("mongo_test_os_release_missing");
+    std::fs::create_dir_all(&root).unwrap();
+    assert!(LinuxDistro::detect_in(&root).is_err());
+}
+
+#[test]
+fn test_download_url_uses_distro() {
+    let distro = LinuxDistro::parse("ID=linuxmint\nID_LIKE=\"ubuntu debian\"\nVERSION_ID=\"21.2\"\nUBUNTU_CODENAME=jammy\n");
+    let url = get_download_url_for("7.0.2", &Os::Linux, &Arch::X86_64, Some(&distro)).unwrap();
+    assert_eq!(url.url, "https://fastdl.mongodb.org/linux/mongodb-linux-x86_64-ubuntu2204-7.0.2.tgz");
+    assert_eq!(url.filename, "mongodb-linux-x86_64-ubuntu2204-7.0.2.tgz");
+
+    let url = get_download_url_for("7.0.2", &Os::MacOs, &Arch::Aarch64, None).unwrap();
+    assert_eq!(url.url, "https://fastdl.mongodb.org/osx/mongodb-macos-aarch64-7.0.2.tgz");
+}
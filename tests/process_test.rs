@@ -0,0 +1,324 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{fake_binary, fake_mongod, work_dir, WAITING};
+use mongo_embedded::downloader::Os;
+use mongo_embedded::process::{ClusterRole, MongoProcess, MongodConfig, MongosConfig, ShutdownMethod};
+use mongo_embedded::LogOutput;
+use std::sync::{Arc, Mutex};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+// The fake mongod records a clean shutdown in its --dbpath when it
+// receives SIGTERM.
+const GRACEFUL: &str = r#"trap 'touch "$4/terminated"; exit 0' TERM
+while true; do sleep 0.05; done"#;
+
+fn start(root: &Path, db_path: &Path) -> MongoProcess {
+    let process = MongoProcess::start(root, &Os::Linux, &MongodConfig::new(0, db_path), String::new()).unwrap();
+    // Give the shell time to install its trap
+    std::thread::sleep(Duration::from_millis(200));
+    process
+}
+
+fn is_running(pid: u32) -> bool {
+    // Signal 0 only checks that the process exists
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+#[test]
+fn test_drop_shuts_down_gracefully() {
+    let dir = work_dir("process_drop");
+    let db_path = dir.join("db");
+    let process = start(&fake_mongod(&dir, GRACEFUL), &db_path);
+    let pid = process.pid().unwrap();
+
+    drop(process);
+    assert!(db_path.join("terminated").exists(), "mongod should get SIGTERM first");
+    assert!(!is_running(pid));
+}
+
+#[test]
+fn test_drop_after_panic() {
+    let dir = work_dir("process_panic");
+    let db_path = dir.join("db");
+    let root = fake_mongod(&dir, GRACEFUL);
+
+    let pid = std::panic::catch_unwind(|| {
+        let process = start(&root, &db_path);
+        std::panic::panic_any(process.pid().unwrap());
+    })
+    .unwrap_err()
+    .downcast::<u32>()
+    .map(|pid| *pid)
+    .unwrap();
+
+    assert!(!is_running(pid), "A panicking test must not leave mongod behind");
+}
+
+#[test]
+fn test_drop_kills_unresponsive_process() {
+    let dir = work_dir("process_unresponsive");
+    let db_path = dir.join("db");
+    let process = start(&fake_mongod(&dir, "trap '' TERM\nwhile true; do sleep 0.05; done"), &db_path);
+    let pid = process.pid().unwrap();
+
+    let started = Instant::now();
+    drop(process);
+    assert!(!is_running(pid));
+    assert!(started.elapsed() >= Duration::from_secs(5), "SIGKILL should only follow the grace period");
+}
+
+#[test]
+fn test_detach_keeps_process_running() {
+    let dir = work_dir("process_detach");
+    let db_path = dir.join("db");
+    let mut config = MongodConfig::new(0, &db_path);
+    config.log_output = LogOutput::DbPathFile;
+    let mut process = MongoProcess::start(&fake_mongod(&dir, GRACEFUL), &Os::Linux, &config, String::new()).unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+
+    let pid = process.detach().unwrap();
+    assert_eq!(process.pid(), None);
+    drop(process);
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(is_running(pid));
+    assert!(!db_path.join("terminated").exists());
+
+    unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+}
+
+#[test]
+fn test_detach_with_buffered_log() {
+    let dir = work_dir("process_detach_buffer");
+    let db_path = dir.join("db");
+    // Writing into a pipe nobody reads any more would kill the shell
+    let script = "echo started\nwhile true; do sleep 0.05; echo tick; done";
+    let mut process = start(&fake_mongod(&dir, script), &db_path);
+    assert_eq!(process.recent_logs(1000).first().map(String::as_str), Some("started"));
+
+    let pid = process.detach().unwrap();
+    drop(process);
+    let written = std::fs::metadata(db_path.join("mongod.stdout")).unwrap().len();
+    std::thread::sleep(Duration::from_millis(300));
+    assert!(is_running(pid));
+    assert!(std::fs::metadata(db_path.join("mongod.stdout")).unwrap().len() > written);
+
+    unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+}
+
+#[test]
+fn test_detach_after_kill() {
+    let dir = work_dir("process_detach_killed");
+    let mut process = start(&fake_mongod(&dir, GRACEFUL), &dir.join("db"));
+
+    process.kill().unwrap();
+    assert!(process.detach().is_err(), "A killed process cannot be detached");
+}
+
+#[test]
+fn test_kill_then_drop() {
+    let dir = work_dir("process_kill");
+    let mut process = start(&fake_mongod(&dir, GRACEFUL), &dir.join("db"));
+    let pid = process.pid().unwrap();
+
+    process.kill().unwrap();
+    assert_eq!(process.pid(), None);
+    assert!(!is_running(pid));
+    process.kill().expect("Killing twice is harmless");
+}
+
+// Nothing listens there, so the shutdown command cannot be delivered.
+fn unreachable_uri() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    format!("mongodb://127.0.0.1:{}/", port)
+}
+
+#[tokio::test]
+async fn test_shutdown_falls_back_to_sigterm() {
+    let dir = work_dir("process_shutdown_signal");
+    let db_path = dir.join("db");
+    let mut process = start(&fake_mongod(&dir, GRACEFUL), &db_path);
+    process.connection_string = unreachable_uri();
+    let pid = process.pid().unwrap();
+
+    let method = process.shutdown(Duration::from_millis(500)).await.unwrap();
+    assert_eq!(method, ShutdownMethod::Signal);
+    assert!(db_path.join("terminated").exists());
+    assert!(!is_running(pid));
+    assert_eq!(process.pid(), None);
+}
+
+#[tokio::test]
+async fn test_shutdown_kills_as_last_resort() {
+    let dir = work_dir("process_shutdown_kill");
+    let mut process = start(&fake_mongod(&dir, "trap '' TERM\nwhile true; do sleep 0.05; done"), &dir.join("db"));
+    process.connection_string = unreachable_uri();
+    let pid = process.pid().unwrap();
+
+    let method = process.shutdown(Duration::from_millis(300)).await.unwrap();
+    assert_eq!(method, ShutdownMethod::Killed);
+    assert!(!is_running(pid));
+}
+
+#[tokio::test]
+async fn test_shutdown_after_exit() {
+    let dir = work_dir("process_shutdown_exited");
+    let mut process = start(&fake_mongod(&dir, "exit 0"), &dir.join("db"));
+
+    assert_eq!(process.shutdown(Duration::from_millis(300)).await.unwrap(), ShutdownMethod::AlreadyExited);
+    assert_eq!(process.shutdown(Duration::from_millis(300)).await.unwrap(), ShutdownMethod::AlreadyExited);
+}
+
+const CHATTY: &str = r#"for i in $(seq 1 50); do echo "line $i"; done
+while true; do sleep 0.05; done"#;
+
+fn start_with_logs(root: &Path, db_path: &Path, log_output: LogOutput) -> MongoProcess {
+    let config = MongodConfig { log_output, ..MongodConfig::new(0, db_path) };
+    MongoProcess::start(root, &Os::Linux, &config, String::new()).unwrap()
+}
+
+fn wait_for_logs(process: &MongoProcess, lines: usize) -> Vec<String> {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let logs = process.recent_logs(100);
+        if logs.len() >= lines || Instant::now() > deadline {
+            return logs;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn test_logs_kept_in_ring_buffer() {
+    let dir = work_dir("process_log_buffer");
+    let process = start_with_logs(&fake_mongod(&dir, CHATTY), &dir.join("db"), LogOutput::Buffer(10));
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while process.recent_logs(1) != vec!["line 50"] && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    let logs = process.recent_logs(100);
+    assert_eq!(logs.len(), 10);
+    assert_eq!(logs.first().unwrap(), "line 41");
+    assert_eq!(logs.last().unwrap(), "line 50");
+    assert_eq!(process.recent_logs(2), vec!["line 49", "line 50"]);
+}
+
+#[test]
+fn test_logs_forwarded_to_callback() {
+    let dir = work_dir("process_log_callback");
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let sink = seen.clone();
+    let process = start_with_logs(
+        &fake_mongod(&dir, CHATTY),
+        &dir.join("db"),
+        LogOutput::Callback(Arc::new(move |line, _| sink.lock().unwrap().push(line.to_string()))),
+    );
+
+    assert_eq!(wait_for_logs(&process, 50).len(), 50);
+    let seen = seen.lock().unwrap();
+    assert_eq!(seen.len(), 50);
+    assert_eq!(seen[0], "line 1");
+}
+
+#[test]
+fn test_callback_gets_parsed_entries() {
+    let dir = work_dir("process_log_callback_parsed");
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let sink = seen.clone();
+    let script = format!("echo 'starting'\necho '{}'\nwhile true; do sleep 0.05; done", WAITING);
+    let process = start_with_logs(
+        &fake_mongod(&dir, &script),
+        &dir.join("db"),
+        LogOutput::Callback(Arc::new(move |_, entry| sink.lock().unwrap().push(entry.map(|e| e.id)))),
+    );
+
+    wait_for_logs(&process, 2);
+    assert_eq!(*seen.lock().unwrap(), vec![None, Some(23016)]);
+}
+
+#[test]
+fn test_logs_written_to_db_path() {
+    let dir = work_dir("process_log_file");
+    let db_path = dir.join("db");
+    // Writes to whatever follows --logpath, like mongod
+    let script = r#"while [ $# -gt 0 ]; do
+  if [ "$1" = "--logpath" ]; then echo "first" >> "$2"; echo "second" >> "$2"; fi
+  shift
+done
+while true; do sleep 0.05; done"#;
+    let process = start_with_logs(&fake_mongod(&dir, script), &db_path, LogOutput::DbPathFile);
+
+    assert_eq!(wait_for_logs(&process, 2), vec!["first", "second"]);
+    assert!(db_path.join("mongod.log").exists());
+}
+
+#[test]
+fn test_discarded_logs() {
+    let dir = work_dir("process_log_discard");
+    let process = start_with_logs(&fake_mongod(&dir, CHATTY), &dir.join("db"), LogOutput::Discard);
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(process.recent_logs(10).is_empty());
+}
+
+#[tokio::test]
+async fn test_subscribe_to_log_entries() {
+    let dir = work_dir("process_log_subscribe");
+    // Gives the test time to subscribe before anything is logged
+    let script = r#"sleep 0.3
+echo 'plain text'
+echo '{"t":{"$date":"2024-05-02T09:14:03.512+00:00"},"s":"I","c":"INDEX","id":20447,"ctx":"conn3","msg":"Index build: done building","attr":{"index":"name_1"}}'
+echo '{"t":{"$date":"2024-05-02T09:14:03.600+00:00"},"s":"W","c":"COMMAND","id":51803,"ctx":"conn3","msg":"Slow query","attr":{"durationMillis":250}}'
+while true; do sleep 0.05; done"#;
+    let process = start_with_logs(&fake_mongod(&dir, script), &dir.join("db"), LogOutput::default());
+
+    let mut events = process.subscribe_logs();
+    let receive = async {
+        let index = events.recv().await.unwrap();
+        let slow = events.recv().await.unwrap();
+        (index, slow)
+    };
+    let (index, slow) = tokio::time::timeout(Duration::from_secs(5), receive).await.expect("No log entries received");
+
+    assert_eq!(index.component, "INDEX");
+    assert_eq!(index.attr("index").and_then(|i| i.as_str()), Some("name_1"));
+    assert_eq!(slow.id, 51803);
+    assert_eq!(slow.severity, mongo_embedded::Severity::Warning);
+    // Unparseable lines still reach the buffer
+    assert_eq!(process.recent_logs(3)[0], "plain text");
+}
+
+// Echoes its arguments as its only log line.
+const ECHO_ARGS: &str = r#"echo "$@"
+while true; do sleep 0.05; done"#;
+
+#[test]
+fn test_shard_server_role() {
+    let dir = work_dir("process_shardsvr");
+    let config = MongodConfig { cluster_role: Some(ClusterRole::ShardServer), ..MongodConfig::new(0, &dir.join("db")) };
+    let process = MongoProcess::start(&fake_mongod(&dir, ECHO_ARGS), &Os::Linux, &config, String::new()).unwrap();
+
+    let args = wait_for_logs(&process, 1);
+    assert!(args[0].contains("--shardsvr"), "Unexpected arguments: {:?}", args);
+    assert!(!args[0].contains("--configsvr"));
+}
+
+#[test]
+fn test_start_mongos() {
+    let dir = work_dir("process_mongos");
+    let root = fake_mongod(&dir, GRACEFUL);
+    fake_binary(&dir, "mongos", ECHO_ARGS);
+
+    let config = MongosConfig::new(27100, "configRS/127.0.0.1:27019", &dir.join("mongos"));
+    // Found next to mongod, as when mongod comes from PATH
+    let process = MongoProcess::start_mongos(&root.join("bin/mongod"), &Os::Linux, &config, String::new()).unwrap();
+
+    let args = wait_for_logs(&process, 1);
+    assert!(args[0].starts_with("--port 27100 --configdb configRS/127.0.0.1:27019"), "Unexpected arguments: {:?}", args);
+    assert!(!args[0].contains("--dbpath"), "mongos takes no db path");
+    assert_eq!(process.port, 27100);
+}
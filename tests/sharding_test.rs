@@ -0,0 +1,38 @@
+mod common;
+
+#[cfg(unix)]
+use common::{recorded_args, recording_embedded, work_dir};
+use mongo_embedded::{MongoEmbedded, MongoShardedCluster, ShardedClusterConfig};
+
+#[test]
+fn test_config_defaults() {
+    let config = ShardedClusterConfig::new(2);
+    assert_eq!(config, ShardedClusterConfig { shards: 2, shard_members: 1, config_servers: 1, routers: 1 });
+
+    let config = config.set_shard_members(3).set_config_servers(3).set_routers(2);
+    assert_eq!((config.shard_members, config.config_servers, config.routers), (3, 3, 2));
+}
+
+#[tokio::test]
+async fn test_cluster_needs_shards_and_routers() {
+    let mongo = MongoEmbedded::new("7.0.2").unwrap();
+    for config in [ShardedClusterConfig::new(0), ShardedClusterConfig::new(1).set_routers(0)] {
+        let err = MongoShardedCluster::start(&mongo, config).await.err().expect("The cluster cannot start");
+        assert!(err.to_string().contains("at least one shard"), "Unexpected error: {}", err);
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_config_servers_start_first() {
+    let dir = work_dir("sharding_config");
+    let mongo = recording_embedded(&dir);
+
+    let err = MongoShardedCluster::start(&mongo, ShardedClusterConfig::new(2)).await.err().expect("A fake mongod cannot be initiated");
+    assert!(err.to_string().contains("replSetInitiate"), "Unexpected error: {}", err);
+
+    let args = recorded_args(&dir.join("db/config/member0"));
+    assert!(args.contains("--replSet configRS"), "Unexpected arguments: {}", args);
+    assert!(args.contains("--configsvr"), "Unexpected arguments: {}", args);
+    assert!(!dir.join("db/shard0").exists(), "Shards must wait for the config servers");
+}
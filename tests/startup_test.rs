@@ -0,0 +1,247 @@
+#![cfg(unix)]
+
+mod common;
+
+use common::{fake_mongo_server, fake_mongod, work_dir, WAITING};
+use mongo_embedded::{LogOutput, MongoEmbedded, OfflineSource, ReadinessStrategy, StartError};
+use std::time::{Duration, Instant};
+
+// MongoEmbedded running a fake mongod from an offline directory.
+fn embedded(name: &str, script: &str) -> MongoEmbedded {
+    let dir = work_dir(&format!("startup_{}", name));
+    let root = fake_mongod(&dir, script);
+
+    // Nothing listens on the port, so only the log can report readiness
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    MongoEmbedded::new("7.0.2").unwrap()
+        .set_offline(OfflineSource::Directory(root))
+        .set_db_path(dir.join("db"))
+        .set_port(port)
+}
+
+#[tokio::test]
+async fn test_ready_on_log_event() {
+    let mongo = embedded("log_event", &format!("sleep 0.2\necho '{}'\nwhile true; do sleep 0.05; done", WAITING))
+        .set_readiness_strategy(ReadinessStrategy::LogEvent);
+
+    let started = Instant::now();
+    let process = mongo.start().await.expect("Start should succeed once the event is logged");
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(process.pid().is_some());
+}
+
+#[tokio::test]
+async fn test_log_event_already_in_buffer() {
+    // Logged before anyone could subscribe
+    let mongo = embedded("log_event_early", &format!("echo '{}'\nwhile true; do sleep 0.05; done", WAITING))
+        .set_readiness_strategy(ReadinessStrategy::LogEvent);
+
+    mongo.start().await.expect("Start should find the event in the buffer");
+}
+
+#[tokio::test]
+async fn test_log_event_from_log_file() {
+    let script = format!(
+        r#"while [ $# -gt 0 ]; do
+  if [ "$1" = "--logpath" ]; then sleep 0.2; echo '{}' >> "$2"; fi
+  shift
+done
+while true; do sleep 0.05; done"#,
+        WAITING
+    );
+    let mongo = embedded("log_event_file", &script)
+        .set_readiness_strategy(ReadinessStrategy::LogEvent)
+        .set_log_output(LogOutput::DbPathFile);
+
+    mongo.start().await.expect("Start should watch the log file");
+}
+
+#[tokio::test]
+async fn test_early_exit_fails_immediately() {
+    for strategy in [ReadinessStrategy::LogEvent, ReadinessStrategy::Ping] {
+        let mongo = embedded("crash", "echo 'Fatal assertion in storage engine'\nexit 3")
+            .set_readiness_strategy(strategy)
+            .set_startup_timeout(Duration::from_secs(60));
+
+        let started = Instant::now();
+        let err = mongo.start().await.err().expect("Start should fail when mongod exits");
+        assert!(started.elapsed() < Duration::from_secs(10), "{:?} waited for the timeout", strategy);
+
+        assert!(matches!(err.downcast_ref::<StartError>(), Some(StartError::Exited { .. })));
+        let message = err.to_string();
+        assert!(message.contains("exit status: 3"), "Unexpected error: {}", message);
+        assert!(message.contains("Fatal assertion in storage engine"), "Logs missing: {}", message);
+    }
+}
+
+#[tokio::test]
+async fn test_startup_timeout() {
+    let mongo = embedded("timeout", "echo 'starting'\nwhile true; do sleep 0.05; done")
+        .set_readiness_strategy(ReadinessStrategy::LogEvent)
+        .set_startup_timeout(Duration::from_millis(500));
+
+    let started = Instant::now();
+    let err = mongo.start().await.err().expect("Start should time out");
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert!(matches!(err.downcast_ref::<StartError>(), Some(StartError::Timeout { .. })));
+    assert!(err.to_string().starts_with("Timed out waiting for MongoDB to start"));
+    assert!(err.to_string().contains("starting"));
+}
+
+#[tokio::test]
+async fn test_log_event_needs_logs() {
+    let mongo = embedded("discard", "while true; do sleep 0.05; done")
+        .set_readiness_strategy(ReadinessStrategy::LogEvent)
+        .set_log_output(LogOutput::Discard);
+
+    let err = mongo.start().await.err().expect("Start should refuse to wait without logs");
+    assert!(err.to_string().contains("Discard") || err.to_string().contains("discarded"));
+}
+
+async fn start_error(name: &str, script: &str) -> StartError {
+    let mongo = embedded(name, script).set_readiness_strategy(ReadinessStrategy::LogEvent);
+    let err = mongo.start().await.err().expect("Start should fail");
+    err.downcast::<StartError>().unwrap_or_else(|e| panic!("Expected a StartError, got: {}", e))
+}
+
+#[tokio::test]
+async fn test_address_in_use() {
+    let line = r#"{"t":{"$date":"2024-05-02T09:14:03.512+00:00"},"s":"E","c":"NETWORK","id":23024,"ctx":"initandlisten","msg":"Failed to set up listener","attr":{"error":{"code":9001,"codeName":"SocketException","errmsg":"Address already in use"}}}"#;
+    let error = start_error("address_in_use", &format!("echo '{}'\nexit 48", line)).await;
+    assert!(matches!(error, StartError::AddressInUse { .. }), "Unexpected error: {}", error);
+    assert!(error.logs()[0].contains("Failed to set up listener"));
+}
+
+#[tokio::test]
+async fn test_db_path_locked_from_stderr() {
+    let script = "echo 'Unable to lock the lock file: Resource temporarily unavailable. Another mongod instance is already running on the dbpath' >&2\nexit 100";
+    let error = start_error("db_path_locked", script).await;
+    match &error {
+        StartError::DbPathLocked { db_path, logs } => {
+            assert!(db_path.ends_with("db"));
+            assert!(logs.iter().any(|l| l.contains("Another mongod instance")), "stderr missing: {:?}", logs);
+        }
+        other => panic!("Unexpected error: {}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_fcv_mismatch() {
+    let line = r#"{"t":{"$date":"2024-05-02T09:14:03.512+00:00"},"s":"F","c":"CONTROL","id":20573,"ctx":"initandlisten","msg":"Wrong mongod version","attr":{"error":"UPGRADE PROBLEM: Found an invalid featureCompatibilityVersion document"}}"#;
+    let error = start_error("fcv", &format!("echo '{}'\nexit 62", line)).await;
+    assert!(matches!(error, StartError::FcvMismatch { .. }), "Unexpected error: {}", error);
+}
+
+#[tokio::test]
+async fn test_fcv_log_line_is_not_a_mismatch() {
+    let fcv = r#"{"t":{"$date":"2024-05-02T09:14:03.512+00:00"},"s":"I","c":"REPL","id":5853300,"ctx":"initandlisten","msg":"current featureCompatibilityVersion value","attr":{"featureCompatibilityVersion":"7.0"}}"#;
+    let failure = r#"{"t":{"$date":"2024-05-02T09:14:03.514+00:00"},"s":"E","c":"STORAGE","id":22312,"ctx":"initandlisten","msg":"Error creating journal directory","attr":{"error":"Permission denied"}}"#;
+    let error = start_error("fcv_benign", &format!("echo '{}'\necho '{}'\nexit 100", fcv, failure)).await;
+    assert!(matches!(error, StartError::Exited { .. }), "Unexpected error: {}", error);
+}
+
+#[tokio::test]
+async fn test_unsupported_cpu() {
+    let error = start_error("sigill", "kill -ILL $$").await;
+    assert!(matches!(error, StartError::UnsupportedCpu { .. }), "Unexpected error: {}", error);
+}
+
+// Records the --port it was given (the second argument) in its db path and
+// claims the address is in use until `$4/attempts` has `fail_times` lines.
+fn flaky_port_script(fail_times: usize) -> String {
+    let in_use = r#"{"t":{"$date":"2024-05-02T09:14:03.512+00:00"},"s":"E","c":"NETWORK","id":23024,"ctx":"initandlisten","msg":"Failed to set up listener","attr":{"error":{"errmsg":"Address already in use"}}}"#;
+    format!(
+        r#"mkdir -p "$4"
+echo "$2" >> "$4/attempts"
+if [ $(wc -l < "$4/attempts") -le {} ]; then echo '{}'; exit 48; fi
+echo '{}'
+while true; do sleep 0.05; done"#,
+        fail_times, in_use, WAITING
+    )
+}
+
+fn attempted_ports(mongo: &MongoEmbedded) -> Vec<u16> {
+    std::fs::read_to_string(mongo.db_path.join("attempts"))
+        .unwrap()
+        .lines()
+        .map(|l| l.parse().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_auto_port_retries_when_taken() {
+    let mongo = embedded("auto_port", &flaky_port_script(2))
+        .set_readiness_strategy(ReadinessStrategy::LogEvent)
+        .set_port_auto();
+
+    let process = mongo.start().await.expect("Start should retry on another port");
+    let ports = attempted_ports(&mongo);
+    assert_eq!(ports.len(), 3);
+    assert!(ports.iter().all(|p| *p != 0));
+    assert_eq!(process.port, ports[2]);
+    assert!(process.connection_string.contains(&format!(":{}/", process.port)));
+}
+
+#[tokio::test]
+async fn test_auto_port_gives_up() {
+    let mongo = embedded("auto_port_gives_up", &flaky_port_script(100))
+        .set_readiness_strategy(ReadinessStrategy::LogEvent)
+        .set_port_auto();
+
+    let err = mongo.start().await.err().expect("Start should give up eventually");
+    assert!(matches!(err.downcast_ref::<StartError>(), Some(StartError::AddressInUse { .. })));
+    assert_eq!(attempted_ports(&mongo).len(), 5);
+}
+
+#[tokio::test]
+async fn test_fixed_port_is_not_retried() {
+    let mongo = embedded("fixed_port", &flaky_port_script(1))
+        .set_readiness_strategy(ReadinessStrategy::LogEvent);
+    let port = mongo.port;
+
+    let err = mongo.start().await.err().expect("A taken fixed port should fail");
+    assert!(matches!(err.downcast_ref::<StartError>(), Some(StartError::AddressInUse { port: p, .. }) if *p == port));
+    assert_eq!(attempted_ports(&mongo), vec![port]);
+}
+
+// Waits for the fake mongod to write its port to `path`, then answers on that
+// port in its place.
+fn serve_port_from(path: &std::path::Path) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !path.exists() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let port: u16 = std::fs::read_to_string(path).unwrap().trim().parse().unwrap();
+    fake_mongo_server(std::net::TcpListener::bind(("127.0.0.1", port)).unwrap());
+}
+
+#[tokio::test]
+async fn test_auto_port_ping_ignores_other_server() {
+    // The first mongod loses its port to another server, which is already
+    // answering pings when the mongod exits. The second keeps its port; as
+    // the fake cannot answer pings itself, the test answers for it.
+    let script = r#"if [ ! -f "$4/lost" ]; then
+  echo "$2" > "$4/port.tmp" && mv "$4/port.tmp" "$4/lost"
+  while [ ! -f "$4/taken" ]; do sleep 0.02; done
+  echo "Address already in use" >&2
+  exit 48
+fi
+echo "$2" > "$4/port.tmp" && mv "$4/port.tmp" "$4/kept"
+while true; do sleep 0.05; done"#;
+    let mongo = embedded("auto_port_ping", script)
+        .set_readiness_strategy(ReadinessStrategy::Ping)
+        .set_port_auto();
+
+    let db_path = mongo.db_path.clone();
+    std::thread::spawn(move || {
+        serve_port_from(&db_path.join("lost"));
+        std::fs::write(db_path.join("taken"), b"").unwrap();
+        serve_port_from(&db_path.join("kept"));
+    });
+
+    let process = mongo.start().await.expect("Start should retry on another port");
+    let lost: u16 = std::fs::read_to_string(mongo.db_path.join("lost")).unwrap().trim().parse().unwrap();
+    let kept: u16 = std::fs::read_to_string(mongo.db_path.join("kept")).unwrap().trim().parse().unwrap();
+    assert_ne!(process.port, lost, "The server that took the port must not count as ours");
+    assert_eq!(process.port, kept);
+}
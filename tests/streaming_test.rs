@@ -0,0 +1,154 @@
+mod common;
+
+use common::{Route, TestServer};
+use mongo_embedded::downloader::{download_and_extract_with_retry, get_download_url, DownloadEvent, RetryPolicy};
+use mongo_embedded::{ChecksumMismatch, InitStatus, MongoEmbedded};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn work_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mongo_test_streaming_{}", name));
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// A tgz large enough to arrive in many chunks.
+fn archive_body() -> Vec<u8> {
+    let mongod: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+    let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::none());
+    let mut builder = tar::Builder::new(encoder);
+    for (name, data) in [
+        ("mongodb-7.0.2/LICENSE-Community.txt", &b"license"[..]),
+        ("mongodb-7.0.2/bin/mongod", &mongod[..]),
+        ("mongodb-7.0.2/bin/mongos", &b"mongos"[..]),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o755);
+        builder.append_data(&mut header, name, data).unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+fn sha256(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+fn fast_retries(max_attempts: u32) -> RetryPolicy {
+    RetryPolicy {
+        max_attempts,
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(50),
+        stall_timeout: Some(Duration::from_millis(500)),
+        overall_timeout: None,
+    }
+}
+
+#[tokio::test]
+async fn test_streams_into_directory() {
+    let server = TestServer::start();
+    let body = archive_body();
+    server.route("/archive.tgz", Route { body: body.clone(), ..Route::default() });
+
+    let out = work_dir("plain").join("out");
+    let mut downloaded = 0;
+    let digest = download_and_extract_with_retry(&server.url("/archive.tgz"), &out, None, &fast_retries(1), |e| {
+        if let DownloadEvent::Progress(p) = e {
+            downloaded = p.downloaded;
+        }
+    })
+    .await
+    .expect("Streaming extraction failed");
+
+    assert_eq!(digest, sha256(&body));
+    assert_eq!(downloaded, body.len() as u64);
+    assert_eq!(std::fs::read(out.join("mongodb-7.0.2/bin/mongos")).unwrap(), b"mongos");
+    assert_eq!(std::fs::read(out.join("mongodb-7.0.2/bin/mongod")).unwrap().len(), 300_000);
+}
+
+#[tokio::test]
+async fn test_interrupted_stream_starts_over() {
+    let server = TestServer::start();
+    let body = archive_body();
+    server.route("/archive.tgz", Route {
+        body: body.clone(),
+        etag: Some("\"v1\"".to_string()),
+        ranges: true,
+        cut_first_response_at: Some(100_000),
+        ..Route::default()
+    });
+
+    let out = work_dir("interrupted").join("out");
+    let mut retries = 0;
+    let digest = download_and_extract_with_retry(&server.url("/archive.tgz"), &out, None, &fast_retries(3), |e| {
+        if matches!(e, DownloadEvent::Retrying(_)) {
+            retries += 1;
+        }
+    })
+    .await
+    .expect("Retried streaming extraction failed");
+
+    assert_eq!(retries, 1);
+    assert_eq!(digest, sha256(&body));
+    let requests = server.requests();
+    assert_eq!(requests.len(), 2);
+    assert!(!requests[1].headers.contains_key("range"), "A stream cannot be resumed");
+}
+
+#[tokio::test]
+async fn test_streams_selected_binaries() {
+    let server = TestServer::start();
+    server.route("/archive.tgz", Route { body: archive_body(), ..Route::default() });
+
+    let out = work_dir("selective").join("out");
+    let binaries = vec!["mongos".to_string()];
+    download_and_extract_with_retry(&server.url("/archive.tgz"), &out, Some(&binaries), &fast_retries(1), |_| {})
+        .await
+        .expect("Streaming extraction failed");
+
+    assert_eq!(std::fs::read(out.join("bin/mongos")).unwrap(), b"mongos");
+    assert_eq!(std::fs::read_dir(&out).unwrap().count(), 1);
+}
+
+#[tokio::test]
+async fn test_streamed_checksum_mismatch_discards_install() {
+    let server = TestServer::start();
+    let mongo_url = get_download_url("7.0.2").unwrap();
+    if !mongo_url.filename.ends_with(".tgz") {
+        return;
+    }
+    server.route(&format!("/{}", mongo_url.path), Route { body: archive_body(), ..Route::default() });
+
+    let dir = work_dir("mismatch");
+    let mut mongo = MongoEmbedded::new("7.0.2").unwrap()
+        .set_download_base_url(&server.url(""))
+        .set_sha256(&"0".repeat(64))
+        .set_streaming_extraction(true);
+    mongo.download_path = dir.join("downloads");
+    mongo.extract_path = dir.join("extracted");
+
+    let mut verified = false;
+    let err = match mongo.start_with_progress(|status| {
+        if matches!(status, InitStatus::VerifyingChecksum) {
+            verified = true;
+        }
+    }).await {
+        Ok(_) => panic!("Start should fail on a checksum mismatch"),
+        Err(e) => e,
+    };
+
+    assert!(verified);
+    let mismatch = err.downcast_ref::<ChecksumMismatch>().expect("Expected a ChecksumMismatch error");
+    assert!(mismatch.path.starts_with(dir.join("extracted")), "Unexpected path: {}", mismatch.path.display());
+    assert!(!mismatch.path.exists(), "The staging directory should be removed");
+    assert!(!mongo.is_installed());
+    assert!(!dir.join("downloads").join(&mongo_url.filename).exists());
+    let leftovers: Vec<_> = std::fs::read_dir(dir.join("extracted"))
+        .map(|entries| entries.map(|e| e.unwrap().file_name()).filter(|n| n != "7.0.2.lock").collect())
+        .unwrap_or_default();
+    assert!(leftovers.is_empty(), "Staging directory left behind: {:?}", leftovers);
+}
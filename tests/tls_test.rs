@@ -0,0 +1,88 @@
+mod common;
+
+use common::work_dir;
+use mongo_embedded::tls::{generate, with_tls};
+use mongo_embedded::TlsFiles;
+use std::path::PathBuf;
+
+#[test]
+fn test_generate_certificates() {
+    let dir = work_dir("tls_generate");
+    let files = generate(&dir, &["localhost".to_string(), "127.0.0.1".to_string()]).unwrap();
+
+    assert_eq!(files.ca_file, dir.join("tls/ca.pem"));
+    let ca = std::fs::read_to_string(&files.ca_file).unwrap();
+    assert_eq!(ca.matches("BEGIN CERTIFICATE").count(), 1);
+    assert!(!ca.contains("PRIVATE KEY"), "The CA key must not be written out");
+
+    // mongod expects the certificate and its key in one file
+    let server = std::fs::read_to_string(&files.certificate_key_file).unwrap();
+    assert_eq!(server.matches("BEGIN CERTIFICATE").count(), 1);
+    assert!(server.contains("BEGIN PRIVATE KEY"));
+
+    // Every start gets a fresh CA
+    generate(&dir, &["localhost".to_string()]).unwrap();
+    assert_ne!(std::fs::read_to_string(&files.ca_file).unwrap(), ca);
+}
+
+#[test]
+fn test_with_tls() {
+    let files = TlsFiles {
+        certificate_key_file: PathBuf::from("/tmp/tls/server.pem"),
+        ca_file: PathBuf::from("/tmp/my certs/ca.pem"),
+    };
+    assert_eq!(
+        with_tls("mongodb://127.0.0.1:27017/?directConnection=true".to_string(), Some(&files)),
+        "mongodb://127.0.0.1:27017/?directConnection=true&tls=true&tlsCAFile=/tmp/my%20certs/ca.pem"
+    );
+    assert_eq!(
+        with_tls("mongodb://u:p@127.0.0.1:27017/".to_string(), Some(&files)),
+        "mongodb://u:p@127.0.0.1:27017/?tls=true&tlsCAFile=/tmp/my%20certs/ca.pem"
+    );
+    assert_eq!(
+        with_tls("mongodb://u:p@%2Ftmp%2Fmongo.sock".to_string(), Some(&files)),
+        "mongodb://u:p@%2Ftmp%2Fmongo.sock/?tls=true&tlsCAFile=/tmp/my%20certs/ca.pem"
+    );
+    assert_eq!(with_tls("mongodb://127.0.0.1:27017/".to_string(), None), "mongodb://127.0.0.1:27017/");
+}
+
+#[tokio::test]
+async fn test_driver_accepts_tls_options() {
+    let files = TlsFiles {
+        certificate_key_file: PathBuf::from("/tmp/tls/server.pem"),
+        ca_file: PathBuf::from("/tmp/my certs/ca.pem"),
+    };
+    let uri = with_tls("mongodb://127.0.0.1:27017/".to_string(), Some(&files));
+    let options = mongodb::options::ClientOptions::parse(&uri).await.unwrap();
+    match options.tls {
+        Some(mongodb::options::Tls::Enabled(tls)) => {
+            assert_eq!(tls.ca_file_path, Some(PathBuf::from("/tmp/my certs/ca.pem")));
+        }
+        other => panic!("TLS not enabled: {:?}", other),
+    }
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_mongod_requires_tls() {
+    use common::{recorded_args, recording_embedded};
+    use mongo_embedded::TlsOptions;
+
+    let dir = work_dir("tls_mongod");
+    let mongo = recording_embedded(&dir).set_port_auto().set_tls(TlsOptions::Generated);
+
+    let process = mongo.start().await.unwrap();
+    let tls = dir.join("db/tls");
+    let args = recorded_args(&dir.join("db"));
+    let expected = format!(
+        "--tlsMode requireTLS --tlsCertificateKeyFile {} --tlsCAFile {} --tlsAllowConnectionsWithoutCertificates",
+        tls.join("server.pem").display(),
+        tls.join("ca.pem").display()
+    );
+    assert!(args.contains(&expected), "Unexpected arguments: {}", args);
+    assert!(
+        process.connection_string.ends_with(&format!("tls=true&tlsCAFile={}", tls.join("ca.pem").display())),
+        "Unexpected connection string: {}",
+        process.connection_string
+    );
+}